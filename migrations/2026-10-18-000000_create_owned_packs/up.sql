@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS owned_packs (
+  user_id BIGINT NOT NULL,
+  pack TEXT NOT NULL,
+  PRIMARY KEY (user_id, pack)
+);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS song_aliases (
+  song_id INTEGER NOT NULL REFERENCES songs(id),
+  alias TEXT NOT NULL,
+  PRIMARY KEY (song_id, alias)
+);
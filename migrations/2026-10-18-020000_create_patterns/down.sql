@@ -0,0 +1,64 @@
+-- This file should undo anything in `up.sql`
+
+CREATE TABLE songs_old (
+  id INTEGER NOT NULL PRIMARY KEY,
+  title TEXT NOT NULL,
+  artist TEXT NOT NULL,
+  min_bpm FLOAT,
+  max_bpm FLOAT NOT NULL,
+  category TEXT NOT NULL,
+  dlc TEXT,
+  four_button_0 INTEGER NOT NULL,
+  four_button_1 INTEGER,
+  four_button_2 INTEGER,
+  four_button_3 INTEGER,
+  five_button_0 INTEGER NOT NULL,
+  five_button_1 INTEGER,
+  five_button_2 INTEGER,
+  five_button_3 INTEGER,
+  six_button_0 INTEGER NOT NULL,
+  six_button_1 INTEGER,
+  six_button_2 INTEGER,
+  six_button_3 INTEGER,
+  eight_button_0 INTEGER NOT NULL,
+  eight_button_1 INTEGER,
+  eight_button_2 INTEGER,
+  eight_button_3 INTEGER
+);
+
+INSERT INTO songs_old
+  SELECT
+    id, title, artist, min_bpm, max_bpm, category, dlc,
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 4 AND difficulty = 0),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 4 AND difficulty = 1),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 4 AND difficulty = 2),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 4 AND difficulty = 3),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 5 AND difficulty = 0),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 5 AND difficulty = 1),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 5 AND difficulty = 2),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 5 AND difficulty = 3),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 6 AND difficulty = 0),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 6 AND difficulty = 1),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 6 AND difficulty = 2),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 6 AND difficulty = 3),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 8 AND difficulty = 0),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 8 AND difficulty = 1),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 8 AND difficulty = 2),
+    (SELECT level FROM patterns WHERE song_id = songs.id AND mode = 8 AND difficulty = 3)
+  FROM songs;
+
+-- `patterns` and `song_aliases` point at `songs`, which has to be empty of
+-- referenced rows before it can be dropped while foreign keys are enforced.
+DROP TABLE IF EXISTS patterns;
+
+CREATE TABLE song_aliases_backup AS SELECT song_id, alias FROM song_aliases;
+
+DELETE FROM song_aliases;
+
+DROP TABLE songs;
+
+ALTER TABLE songs_old RENAME TO songs;
+
+INSERT INTO song_aliases (song_id, alias) SELECT song_id, alias FROM song_aliases_backup;
+
+DROP TABLE song_aliases_backup;
@@ -0,0 +1,69 @@
+-- Your SQL goes here
+
+-- The bundled SQLite is built with SQLITE_DEFAULT_FOREIGN_KEYS=1, so dropping
+-- `songs` deletes its rows and fails while other tables still point at them.
+-- Set the charts and aliases aside until the table is rebuilt without the
+-- legacy level columns.
+CREATE TABLE pattern_levels (
+  song_id INTEGER NOT NULL,
+  mode INTEGER NOT NULL,
+  difficulty INTEGER NOT NULL,
+  level INTEGER NOT NULL
+);
+
+INSERT INTO pattern_levels (song_id, mode, difficulty, level)
+  SELECT id, 4, 0, four_button_0 FROM songs UNION ALL
+  SELECT id, 4, 1, four_button_1 FROM songs WHERE four_button_1 IS NOT NULL UNION ALL
+  SELECT id, 4, 2, four_button_2 FROM songs WHERE four_button_2 IS NOT NULL UNION ALL
+  SELECT id, 4, 3, four_button_3 FROM songs WHERE four_button_3 IS NOT NULL UNION ALL
+  SELECT id, 5, 0, five_button_0 FROM songs UNION ALL
+  SELECT id, 5, 1, five_button_1 FROM songs WHERE five_button_1 IS NOT NULL UNION ALL
+  SELECT id, 5, 2, five_button_2 FROM songs WHERE five_button_2 IS NOT NULL UNION ALL
+  SELECT id, 5, 3, five_button_3 FROM songs WHERE five_button_3 IS NOT NULL UNION ALL
+  SELECT id, 6, 0, six_button_0 FROM songs UNION ALL
+  SELECT id, 6, 1, six_button_1 FROM songs WHERE six_button_1 IS NOT NULL UNION ALL
+  SELECT id, 6, 2, six_button_2 FROM songs WHERE six_button_2 IS NOT NULL UNION ALL
+  SELECT id, 6, 3, six_button_3 FROM songs WHERE six_button_3 IS NOT NULL UNION ALL
+  SELECT id, 8, 0, eight_button_0 FROM songs UNION ALL
+  SELECT id, 8, 1, eight_button_1 FROM songs WHERE eight_button_1 IS NOT NULL UNION ALL
+  SELECT id, 8, 2, eight_button_2 FROM songs WHERE eight_button_2 IS NOT NULL UNION ALL
+  SELECT id, 8, 3, eight_button_3 FROM songs WHERE eight_button_3 IS NOT NULL;
+
+CREATE TABLE song_aliases_backup AS SELECT song_id, alias FROM song_aliases;
+
+DELETE FROM song_aliases;
+
+CREATE TABLE songs_new (
+  id INTEGER NOT NULL PRIMARY KEY,
+  title TEXT NOT NULL,
+  artist TEXT NOT NULL,
+  min_bpm FLOAT,
+  max_bpm FLOAT NOT NULL,
+  category TEXT NOT NULL,
+  dlc TEXT
+);
+
+INSERT INTO songs_new (id, title, artist, min_bpm, max_bpm, category, dlc)
+  SELECT id, title, artist, min_bpm, max_bpm, category, dlc FROM songs;
+
+DROP TABLE songs;
+
+ALTER TABLE songs_new RENAME TO songs;
+
+CREATE TABLE IF NOT EXISTS patterns (
+  song_id INTEGER NOT NULL REFERENCES songs(id),
+  mode INTEGER NOT NULL,
+  difficulty INTEGER NOT NULL,
+  level INTEGER NOT NULL,
+  note_count INTEGER,
+  PRIMARY KEY (song_id, mode, difficulty)
+);
+
+INSERT INTO patterns (song_id, mode, difficulty, level)
+  SELECT song_id, mode, difficulty, level FROM pattern_levels;
+
+DROP TABLE pattern_levels;
+
+INSERT INTO song_aliases (song_id, alias) SELECT song_id, alias FROM song_aliases_backup;
+
+DROP TABLE song_aliases_backup;
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+
+CREATE TABLE patterns_old (
+  song_id INTEGER NOT NULL REFERENCES songs(id),
+  mode INTEGER NOT NULL,
+  difficulty INTEGER NOT NULL,
+  level INTEGER NOT NULL,
+  note_count INTEGER,
+  PRIMARY KEY (song_id, mode, difficulty)
+);
+
+INSERT INTO patterns_old (song_id, mode, difficulty, level, note_count)
+  SELECT song_id, mode, difficulty, level, note_count FROM patterns;
+
+DROP TABLE patterns;
+
+ALTER TABLE patterns_old RENAME TO patterns;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE patterns ADD COLUMN long_note_count INTEGER;
+ALTER TABLE patterns ADD COLUMN designer TEXT;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS records (
+  user_id BIGINT NOT NULL,
+  song_id INTEGER NOT NULL REFERENCES songs(id),
+  mode INTEGER NOT NULL,
+  difficulty INTEGER NOT NULL,
+  rate DOUBLE NOT NULL,
+  max_combo BOOLEAN NOT NULL,
+  cleared_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (user_id, song_id, mode, difficulty)
+);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS guild_scores (
+  guild_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  song_id INTEGER NOT NULL REFERENCES songs(id),
+  mode INTEGER NOT NULL,
+  difficulty INTEGER NOT NULL,
+  rate DOUBLE NOT NULL,
+  max_combo BOOLEAN NOT NULL,
+  submitted_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (guild_id, user_id, song_id, mode, difficulty)
+);
@@ -0,0 +1,524 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use diesel::sqlite::SqliteConnection;
+use serenity::prelude::TypeMapKey;
+
+use crate::db::models::{
+    BpmRange, ButtonMode, ChartFilter, Difficulty, PackCount, PackFilter, Pattern, Song, SongAlias,
+    SongOrder,
+};
+use crate::db::Error;
+use crate::fuzzy::TitleIndex;
+use crate::hangul::{is_choseong_query, to_choseong};
+
+/// Every song held in memory, indexed for the lookups the commands make.
+/// The songs never change at runtime, so `reload` builds a new catalog and
+/// swaps the `Arc` in `Context::data` instead of mutating this one.
+pub struct Catalog {
+    /// Every song, ordered by id.
+    songs: Vec<Song>,
+    ids: HashMap<i32, usize>,
+    /// Songs by normalized title.
+    titles: HashMap<String, Vec<usize>>,
+    /// Songs with at least one chart of the given button mode and level.
+    charts: BTreeMap<(i32, i32), BTreeSet<usize>>,
+    /// Normalized aliases and the song they belong to.
+    aliases: Vec<(usize, String)>,
+    fuzzy: TitleIndex,
+}
+
+impl TypeMapKey for Catalog {
+    type Value = Arc<Catalog>;
+}
+
+/// Ranks how well `text` matches `query`: 0 for an exact match, 1 for a prefix
+/// match and 2 for any other substring.
+fn match_rank(text: &str, query: &str) -> Option<u8> {
+    if text == query {
+        Some(0)
+    } else if text.starts_with(query) {
+        Some(1)
+    } else if text.contains(query) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Lowercases `name` and strips everything but letters and digits.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns whether the words of `query` appear in `text` in order, like the
+/// `LIKE '%word%word%'` title search this replaces.
+fn contains_words(text: &str, query: &str) -> bool {
+    let mut rest = text;
+    for word in query.split_whitespace() {
+        match rest.find(word) {
+            Some(position) => rest = &rest[position + word.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+impl Catalog {
+    pub fn new(mut songs: Vec<Song>, aliases: Vec<SongAlias>) -> Self {
+        songs.sort_by_key(|song| song.id);
+
+        let mut ids = HashMap::new();
+        let mut titles: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut charts: BTreeMap<(i32, i32), BTreeSet<usize>> = BTreeMap::new();
+        for (index, song) in songs.iter().enumerate() {
+            ids.insert(song.id, index);
+            titles
+                .entry(normalize_name(&song.title))
+                .or_default()
+                .push(index);
+            for pattern in &song.patterns {
+                charts
+                    .entry((pattern.mode, pattern.level))
+                    .or_default()
+                    .insert(index);
+            }
+        }
+        let aliases = aliases
+            .into_iter()
+            .filter_map(|alias| Some((*ids.get(&alias.song_id)?, normalize_name(&alias.alias))))
+            .collect();
+
+        Catalog {
+            fuzzy: TitleIndex::new(&songs),
+            songs,
+            ids,
+            titles,
+            charts,
+            aliases,
+        }
+    }
+
+    pub fn load(conn: &SqliteConnection) -> Result<Self, Error> {
+        Ok(Self::new(Song::all(conn)?, SongAlias::all(conn)?))
+    }
+
+    pub fn song_count(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn song(&self, id: i32) -> Option<&Song> {
+        self.ids.get(&id).map(|&index| &self.songs[index])
+    }
+
+    /// Returns the songs with the given ids in the order of `ids`.
+    pub fn by_ids(&self, ids: &[i32], packs: &PackFilter) -> Vec<Song> {
+        ids.iter()
+            .filter_map(|&id| self.song(id))
+            .filter(|song| packs.matches(song))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every song whose title contains `query`, ranked so that exact
+    /// matches come first, prefix matches next and other substrings last.
+    pub fn search_title(&self, query: &str, packs: &PackFilter) -> Vec<Song> {
+        let query = query.trim().replace("%", "");
+        if query.is_empty() {
+            return vec![];
+        }
+        if is_choseong_query(&query) {
+            return self.search_choseong(&query, packs);
+        }
+
+        let lowered = query.to_lowercase();
+        let mut ranks: HashMap<usize, u8> = HashMap::new();
+        for (index, song) in self.songs.iter().enumerate() {
+            let title = song.title.to_lowercase();
+            if contains_words(&title, &lowered) {
+                ranks.insert(index, match_rank(&title, &lowered).unwrap_or(2));
+            }
+        }
+
+        // Titles and aliases are also compared without spaces and punctuation,
+        // so that "stay with me" and "staywithme" find the same song.
+        let normalized = normalize_name(&query);
+        if !normalized.is_empty() {
+            for &index in self.titles.get(&normalized).into_iter().flatten() {
+                ranks.insert(index, 0);
+            }
+            for (index, alias) in &self.aliases {
+                if let Some(rank) = match_rank(alias, &normalized) {
+                    let best = ranks.entry(*index).or_insert(rank);
+                    *best = (*best).min(rank);
+                }
+            }
+        }
+
+        self.ranked(ranks, packs)
+    }
+
+    /// Returns the songs whose title or an alias equals `query`, ignoring case,
+    /// spaces and punctuation.
+    pub fn exact_title(&self, query: &str) -> Vec<Song> {
+        let normalized = normalize_name(query);
+        if normalized.is_empty() {
+            return vec![];
+        }
+        let mut indices: BTreeSet<usize> = self
+            .titles
+            .get(&normalized)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        indices.extend(
+            self.aliases
+                .iter()
+                .filter(|(_, alias)| *alias == normalized)
+                .map(|(index, _)| *index),
+        );
+        indices
+            .into_iter()
+            .map(|index| self.songs[index].clone())
+            .collect()
+    }
+
+    /// Returns every song whose title contains the initial consonants in `query`,
+    /// ranked like `search_title`.
+    fn search_choseong(&self, query: &str, packs: &PackFilter) -> Vec<Song> {
+        let query = to_choseong(query);
+        let ranks = self
+            .songs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, song)| {
+                Some((index, match_rank(&to_choseong(&song.title), &query)?))
+            })
+            .collect();
+        self.ranked(ranks, packs)
+    }
+
+    /// Orders ranked songs by rank, then by id.
+    fn ranked(&self, ranks: HashMap<usize, u8>, packs: &PackFilter) -> Vec<Song> {
+        let mut ranked: Vec<(u8, usize)> = ranks
+            .into_iter()
+            .map(|(index, rank)| (rank, index))
+            .filter(|&(_, index)| packs.matches(&self.songs[index]))
+            .collect();
+        ranked.sort();
+        ranked
+            .into_iter()
+            .map(|(_, index)| self.songs[index].clone())
+            .collect()
+    }
+
+    /// Returns the songs whose titles are closest to a mistyped `query`, best match first.
+    pub fn suggest(&self, query: &str, packs: &PackFilter) -> Vec<Song> {
+        self.by_ids(&self.fuzzy.suggest(query), packs)
+    }
+
+    /// Returns every song whose artist contains `query`, ignoring case, spaces
+    /// and punctuation, ordered by artist.
+    pub fn by_artist(&self, query: &str, packs: &PackFilter) -> Vec<Song> {
+        let query = normalize_name(query);
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut contents: Vec<Song> = self
+            .songs
+            .iter()
+            .filter(|song| packs.matches(song))
+            .filter(|song| normalize_name(&song.artist).contains(&query))
+            .cloned()
+            .collect();
+        contents.sort_by(|a, b| a.artist.cmp(&b.artist).then(a.id.cmp(&b.id)));
+        contents
+    }
+
+    /// Returns every song whose category or DLC is `pack`, ordered by id.
+    pub fn by_pack(&self, pack: &str) -> Vec<Song> {
+        self.songs
+            .iter()
+            .filter(|song| song.category == pack || song.dlc.as_deref() == Some(pack))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every category and every DLC with the number of songs in it.
+    pub fn pack_counts(&self) -> (Vec<PackCount>, Vec<PackCount>) {
+        let mut categories = BTreeMap::new();
+        let mut dlcs = BTreeMap::new();
+        for song in &self.songs {
+            *categories.entry(song.category.clone()).or_insert(0) += 1;
+            if let Some(dlc) = &song.dlc {
+                *dlcs.entry(dlc.clone()).or_insert(0) += 1;
+            }
+        }
+        (categories.into_iter().collect(), dlcs.into_iter().collect())
+    }
+
+    pub fn by_bpm(&self, bpm: BpmRange, packs: &PackFilter, offset: i64) -> (i64, Vec<Song>) {
+        let mut contents: Vec<&Song> = self
+            .songs
+            .iter()
+            .filter(|song| bpm.matches(song) && packs.matches(song))
+            .collect();
+        contents.sort_by(|a, b| {
+            a.max_bpm
+                .partial_cmp(&b.max_bpm)
+                .unwrap_or(Ordering::Equal)
+                .then(a.id.cmp(&b.id))
+        });
+        page(contents, offset)
+    }
+
+    pub fn by_level(
+        &self,
+        filter: &ChartFilter,
+        order: SongOrder,
+        offset: i64,
+    ) -> (i64, Vec<Song>) {
+        let mut contents = self.matching(filter);
+        match order {
+            SongOrder::Id => {}
+            SongOrder::Title => {
+                contents.sort_by(|a, b| a.title.cmp(&b.title).then(a.id.cmp(&b.id)))
+            }
+            SongOrder::Bpm => contents.sort_by(|a, b| {
+                a.max_bpm
+                    .partial_cmp(&b.max_bpm)
+                    .unwrap_or(Ordering::Equal)
+                    .then(a.min_bpm.partial_cmp(&b.min_bpm).unwrap_or(Ordering::Equal))
+                    .then(a.id.cmp(&b.id))
+            }),
+            SongOrder::Category => {
+                contents.sort_by(|a, b| a.category.cmp(&b.category).then(a.id.cmp(&b.id)))
+            }
+            SongOrder::NoteCount => {
+                contents.sort_by_key(|song| (Reverse(filter.max_note_count(song)), song.id))
+            }
+        }
+        page(contents, offset)
+    }
+
+    /// Returns every song with at least one chart matched by `filter`, ordered by id.
+    pub fn all_by_level(&self, filter: &ChartFilter) -> Vec<Song> {
+        self.matching(filter).into_iter().cloned().collect()
+    }
+
+    fn matching(&self, filter: &ChartFilter) -> Vec<&Song> {
+        // `BTreeMap::range` panics when the range is reversed.
+        if filter.min_level > filter.max_level {
+            return vec![];
+        }
+        let mut indices: BTreeSet<usize> = BTreeSet::new();
+        for mode in &filter.modes {
+            let levels = (mode.buttons(), filter.min_level)..=(mode.buttons(), filter.max_level);
+            for (_, songs) in self.charts.range(levels) {
+                indices.extend(songs);
+            }
+        }
+        indices
+            .into_iter()
+            .map(|index| &self.songs[index])
+            .filter(|song| filter.matches(song))
+            .collect()
+    }
+
+    /// Returns the charts with a known note count, densest first, along with
+    /// the title of their song.
+    pub fn by_note_count(
+        &self,
+        modes: &[ButtonMode],
+        difficulties: &[Difficulty],
+        offset: i64,
+    ) -> (i64, Vec<(Pattern, String)>) {
+        let mut charts: Vec<(&Pattern, &Song)> = self
+            .songs
+            .iter()
+            .flat_map(|song| song.patterns.iter().map(move |pattern| (pattern, song)))
+            .filter(|(pattern, _)| {
+                pattern.note_count.is_some()
+                    && pattern
+                        .button_mode()
+                        .is_some_and(|mode| modes.contains(&mode))
+                    && pattern
+                        .chart_difficulty()
+                        .is_some_and(|difficulty| difficulties.contains(&difficulty))
+            })
+            .collect();
+        charts.sort_by_key(|(pattern, _)| {
+            (
+                Reverse(pattern.note_count),
+                pattern.song_id,
+                pattern.mode,
+                pattern.difficulty,
+            )
+        });
+
+        let count = charts.len() as i64;
+        let charts = charts
+            .into_iter()
+            .skip(offset as usize)
+            .take(25)
+            .map(|(pattern, song)| (pattern.clone(), song.title.clone()))
+            .collect();
+        (count, charts)
+    }
+}
+
+/// Cuts a 25-song page starting at `offset` out of `contents`.
+fn page(contents: Vec<&Song>, offset: i64) -> (i64, Vec<Song>) {
+    let count = contents.len() as i64;
+    let contents = contents
+        .into_iter()
+        .skip(offset as usize)
+        .take(25)
+        .cloned()
+        .collect();
+    (count, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: i32, title: &str, charts: &[(i32, i32, i32)]) -> Song {
+        Song {
+            id,
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            min_bpm: None,
+            max_bpm: 150.0,
+            category: "RP".to_string(),
+            dlc: None,
+            patterns: charts
+                .iter()
+                .map(|&(mode, difficulty, level)| Pattern {
+                    song_id: id,
+                    mode,
+                    difficulty,
+                    level,
+                    note_count: None,
+                    long_note_count: None,
+                    designer: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn alias(song_id: i32, alias: &str) -> SongAlias {
+        SongAlias {
+            song_id,
+            alias: alias.to_string(),
+        }
+    }
+
+    fn ids(songs: &[Song]) -> Vec<i32> {
+        songs.iter().map(|song| song.id).collect()
+    }
+
+    #[test]
+    fn exact_title_matches_whole_titles_and_aliases() {
+        let catalog = Catalog::new(
+            vec![
+                song(1, "Stay with me", &[]),
+                song(2, "Stay", &[]),
+                song(3, "Fermion", &[]),
+            ],
+            vec![alias(3, "페르미온")],
+        );
+        assert_eq!(ids(&catalog.exact_title("stay")), vec![2]);
+        assert_eq!(ids(&catalog.exact_title("STAYWITHME")), vec![1]);
+        assert_eq!(ids(&catalog.exact_title("페르미온")), vec![3]);
+        assert!(catalog.exact_title("with").is_empty());
+    }
+
+    #[test]
+    fn ranks_exact_then_prefix_then_substring_matches() {
+        let catalog = Catalog::new(
+            vec![
+                song(1, "X-Ray", &[]),
+                song(2, "Raymond", &[]),
+                song(3, "Ray", &[]),
+                song(4, "Sunray Road", &[]),
+            ],
+            vec![],
+        );
+
+        assert_eq!(
+            ids(&catalog.search_title("ray", &PackFilter::default())),
+            vec![3, 2, 1, 4]
+        );
+        assert_eq!(
+            ids(&catalog.search_title("sun road", &PackFilter::default())),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn searches_aliases_and_initial_consonants() {
+        let catalog = Catalog::new(
+            vec![
+                song(1, "Fermion", &[]),
+                song(2, "비상 2", &[]),
+                song(3, "비상", &[]),
+                song(4, "고백, 꽃, 늑대", &[]),
+            ],
+            vec![alias(1, "페르미온"), alias(99, "없는 곡")],
+        );
+        let search = |query: &str| ids(&catalog.search_title(query, &PackFilter::default()));
+
+        assert_eq!(search("페르미"), vec![1]);
+        assert_eq!(search("페르 미온"), vec![1]);
+        assert!(search("없는 곡").is_empty());
+        assert_eq!(search("ㅂㅅ"), vec![3, 2]);
+        assert_eq!(search("ㄲㄴ"), vec![4]);
+    }
+
+    #[test]
+    fn sorted_level_pages_never_overlap() {
+        let songs: Vec<Song> = (1..=60)
+            .map(|id| {
+                let mut song = song(id, ["A", "B", "C"][id as usize % 3], &[(4, 0, 5)]);
+                song.max_bpm = f64::from(id % 4 * 10 + 100);
+                song.category = ["RP", "P1", "P2"][id as usize % 2].to_string();
+                song
+            })
+            .collect();
+        let catalog = Catalog::new(songs, vec![]);
+        let filter = ChartFilter::new(5, 5);
+
+        for &order in &[
+            SongOrder::Id,
+            SongOrder::Title,
+            SongOrder::Bpm,
+            SongOrder::Category,
+            SongOrder::NoteCount,
+        ] {
+            let mut seen = BTreeSet::new();
+            for offset in (0..60).step_by(25) {
+                let (count, page) = catalog.by_level(&filter, order, offset);
+                assert_eq!(count, 60);
+                for song in page {
+                    assert!(seen.insert(song.id), "{:?} repeats song {}", order, song.id);
+                }
+            }
+            assert_eq!(seen.len(), 60, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn reversed_level_range_matches_nothing() {
+        let catalog = Catalog::new(vec![song(1, "Ray", &[(4, 0, 5)])], vec![]);
+        let (count, page) = catalog.by_level(&ChartFilter::new(10, 1), SongOrder::Id, 0);
+        assert_eq!(count, 0);
+        assert!(page.is_empty());
+    }
+}
@@ -1,27 +1,176 @@
+mod error;
 pub mod models;
 pub mod schema;
 
+pub use self::error::Error;
+
 use std::env;
 
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
 use diesel::sqlite::SqliteConnection;
+use diesel_migrations::MigrationConnection;
 use dotenv::dotenv;
+use serenity::prelude::TypeMapKey;
 
 embed_migrations!();
 
-pub fn establish_connection() -> SqliteConnection {
+pub type Pool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub type PooledConnection = r2d2::PooledConnection<ConnectionManager<SqliteConnection>>;
+
+/// Connection pool shared by every command through `Context::data`.
+pub struct ConnectionPool;
+
+impl TypeMapKey for ConnectionPool {
+    type Value = Pool;
+}
+
+/// Lets pooled connections wait for each other's writes instead of failing
+/// with "database is locked".
+#[derive(Debug)]
+struct ConnectionOptions;
+
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+pub fn establish_connection() -> Result<SqliteConnection, Error> {
     if cfg!(test) {
-        let conn = SqliteConnection::establish(":memory:")
-            .unwrap_or_else(|_| panic!("Error creating test database"));
+        let conn = SqliteConnection::establish(":memory:")?;
 
-        let _result = diesel_migrations::run_pending_migrations(&conn);
-        conn
+        run_migrations(&conn)?;
+        Ok(conn)
     } else {
         dotenv().ok();
 
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let database_url = env::var("DATABASE_URL").map_err(|_| Error::MissingUrl)?;
+
+        Ok(SqliteConnection::establish(&database_url)?)
+    }
+}
+
+pub fn establish_pool() -> Result<Pool, Error> {
+    if cfg!(test) {
+        // Every in-memory connection is a database of its own, so keep a single one.
+        let pool = Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(ConnectionOptions))
+            .build(ConnectionManager::new(":memory:"))?;
+
+        run_migrations(&*pool.get()?)?;
+        Ok(pool)
+    } else {
+        dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").map_err(|_| Error::MissingUrl)?;
+
+        Ok(Pool::builder()
+            .connection_customizer(Box::new(ConnectionOptions))
+            .build(ConnectionManager::new(database_url))?)
+    }
+}
+
+/// Runs the embedded migrations the database has not seen yet and returns the
+/// versions that were applied, oldest first.
+pub fn run_migrations(conn: &SqliteConnection) -> Result<Vec<String>, Error> {
+    diesel_migrations::setup_database(conn)?;
+    let previous = conn.previously_run_migration_versions()?;
+
+    embedded_migrations::run(conn)?;
+
+    let mut applied: Vec<String> = conn
+        .previously_run_migration_versions()?
+        .difference(&previous)
+        .cloned()
+        .collect();
+    applied.sort();
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use diesel::dsl::sql;
+    use diesel::sql_types::Integer;
+
+    use self::models::{ButtonMode, Difficulty, Song, SongAlias};
+
+    /// Migrations that predate the `patterns` table.
+    const BASELINE_MIGRATIONS: [&str; 3] = [
+        "2020-07-28-072159_create_songs",
+        "2026-10-18-000000_create_owned_packs",
+        "2026-10-18-010000_create_song_aliases",
+    ];
+
+    fn migrations_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations")
+    }
+
+    /// Returns an in-memory database at the baseline schema holding a song with an
+    /// alias. Foreign keys are enforced because the bundled SQLite is built with
+    /// `SQLITE_DEFAULT_FOREIGN_KEYS=1`, as in production.
+    fn populated_baseline() -> SqliteConnection {
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+        let migrations = BASELINE_MIGRATIONS
+            .iter()
+            .map(|name| diesel_migrations::migration_from(migrations_dir().join(name)).unwrap())
+            .collect::<Vec<_>>();
+        diesel_migrations::run_migrations(&conn, migrations, &mut io::sink()).unwrap();
+
+        conn.batch_execute(
+            "INSERT INTO songs VALUES (1, '비상 ~Stay With Me~', 'Jeon Gun', NULL, 150, 'RP', NULL,
+                 3, 6, 8, NULL, 4, 7, 9, NULL, 5, 8, 11, NULL, 6, 9, 12, 14);
+             INSERT INTO song_aliases VALUES (1, 'bisang');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrates_a_populated_baseline_database() {
+        let conn = populated_baseline();
+        run_migrations(&conn).unwrap();
+
+        let songs = Song::all(&conn).unwrap();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[0].patterns.len(), 13);
+        assert_eq!(
+            songs[0].level(ButtonMode::Four, Difficulty::Normal),
+            Some(3)
+        );
+        assert_eq!(songs[0].level(ButtonMode::Four, Difficulty::SC), None);
+        assert_eq!(songs[0].level(ButtonMode::Eight, Difficulty::SC), Some(14));
+
+        let aliases = SongAlias::all(&conn).unwrap();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases[0].alias, "bisang");
+    }
+
+    #[test]
+    fn reverts_the_patterns_table_on_a_populated_database() {
+        let conn = populated_baseline();
+        run_migrations(&conn).unwrap();
+
+        while conn.latest_run_migration_version().unwrap().as_deref() != Some("20261018010000") {
+            diesel_migrations::revert_latest_migration_in_directory(&conn, &migrations_dir())
+                .unwrap();
+        }
 
-        SqliteConnection::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+        let level = diesel::select(sql::<Integer>("(SELECT eight_button_2 FROM songs)"))
+            .get_result::<i32>(&conn)
+            .unwrap();
+        assert_eq!(level, 12);
+        let aliases = diesel::select(sql::<Integer>("(SELECT COUNT(*) FROM song_aliases)"))
+            .get_result::<i32>(&conn)
+            .unwrap();
+        assert_eq!(aliases, 1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,68 @@
+use std::error;
+use std::fmt;
+
+use diesel::r2d2::PoolError;
+use diesel::result::{ConnectionError, Error as QueryError};
+use diesel_migrations::RunMigrationsError;
+
+/// Error returned by the database layer instead of panicking the shard.
+#[derive(Debug)]
+pub enum Error {
+    /// `DATABASE_URL` is not set.
+    MissingUrl,
+    Connection(ConnectionError),
+    Pool(PoolError),
+    Query(QueryError),
+    Migration(RunMigrationsError),
+    /// The input refers to a chart that does not exist or is malformed.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingUrl => write!(f, "DATABASE_URL must be set"),
+            Error::Connection(why) => write!(f, "Error connecting to database: {}", why),
+            Error::Pool(why) => write!(f, "Error getting a database connection: {}", why),
+            Error::Query(why) => write!(f, "Error querying database: {}", why),
+            Error::Migration(why) => write!(f, "Error running migrations: {}", why),
+            Error::Invalid(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Connection(why) => Some(why),
+            Error::Pool(why) => Some(why),
+            Error::Query(why) => Some(why),
+            Error::Migration(why) => Some(why),
+            Error::MissingUrl | Error::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<ConnectionError> for Error {
+    fn from(why: ConnectionError) -> Self {
+        Error::Connection(why)
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(why: PoolError) -> Self {
+        Error::Pool(why)
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(why: QueryError) -> Self {
+        Error::Query(why)
+    }
+}
+
+impl From<RunMigrationsError> for Error {
+    fn from(why: RunMigrationsError) -> Self {
+        Error::Migration(why)
+    }
+}
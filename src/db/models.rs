@@ -1,13 +1,475 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::Deserialize;
 
+use crate::db::schema::guild_scores;
+use crate::db::schema::owned_packs;
+use crate::db::schema::patterns;
+use crate::db::schema::records;
+use crate::db::schema::song_aliases;
 use crate::db::schema::songs;
 use crate::db::schema::songs::dsl::songs as song_dsl;
+use crate::db::Error;
 
-#[derive(Debug, Deserialize, Queryable, Insertable, AsChangeset)]
-#[serde(rename_all(deserialize = "camelCase"))]
+/// Number of buttons a chart is played with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ButtonMode {
+    Four,
+    Five,
+    Six,
+    Eight,
+}
+
+impl ButtonMode {
+    pub const ALL: [ButtonMode; 4] = [
+        ButtonMode::Four,
+        ButtonMode::Five,
+        ButtonMode::Six,
+        ButtonMode::Eight,
+    ];
+
+    pub fn buttons(self) -> i32 {
+        match self {
+            ButtonMode::Four => 4,
+            ButtonMode::Five => 5,
+            ButtonMode::Six => 6,
+            ButtonMode::Eight => 8,
+        }
+    }
+
+    pub fn from_buttons(buttons: i32) -> Option<Self> {
+        ButtonMode::ALL
+            .iter()
+            .cloned()
+            .find(|mode| mode.buttons() == buttons)
+    }
+}
+
+impl fmt::Display for ButtonMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}B", self.buttons())
+    }
+}
+
+impl FromStr for ButtonMode {
+    type Err = String;
+
+    /// Accepts `4`, `4b`, `4k`, `4버튼` and `4키` style spellings.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let digits = s.trim_end_matches(['b', 'k', '버', '튼', '키']);
+        match digits {
+            "4" => Ok(ButtonMode::Four),
+            "5" => Ok(ButtonMode::Five),
+            "6" => Ok(ButtonMode::Six),
+            "8" => Ok(ButtonMode::Eight),
+            _ => Err(format!("Unknown button mode: {}", s)),
+        }
+    }
+}
+
+/// Difficulty of a chart within a button mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Normal,
+    Hard,
+    Maximum,
+    SC,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Maximum,
+        Difficulty::SC,
+    ];
+
+    /// Position of the difficulty as stored in `patterns.difficulty`, NM being 0.
+    pub fn index(self) -> i32 {
+        match self {
+            Difficulty::Normal => 0,
+            Difficulty::Hard => 1,
+            Difficulty::Maximum => 2,
+            Difficulty::SC => 3,
+        }
+    }
+
+    pub fn from_index(index: i32) -> Option<Self> {
+        Difficulty::ALL
+            .iter()
+            .cloned()
+            .find(|difficulty| difficulty.index() == index)
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let abbreviation = match self {
+            Difficulty::Normal => "NM",
+            Difficulty::Hard => "HD",
+            Difficulty::Maximum => "MX",
+            Difficulty::SC => "SC",
+        };
+        write!(f, "{}", abbreviation)
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "nm" | "normal" | "노말" => Ok(Difficulty::Normal),
+            "hd" | "hard" | "하드" => Ok(Difficulty::Hard),
+            "mx" | "maximum" | "맥시멈" => Ok(Difficulty::Maximum),
+            "sc" | "스칼렛" => Ok(Difficulty::SC),
+            _ => Err(format!("Unknown difficulty: {}", s)),
+        }
+    }
+}
+
+/// Order of level search results. Every order falls back to the song id, so
+/// that pages never overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SongOrder {
+    /// Release order, which is the default.
+    #[default]
+    Id,
+    Title,
+    Bpm,
+    Category,
+    /// Most notes first, counting only the charts matched by the filter.
+    NoteCount,
+}
+
+impl fmt::Display for SongOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SongOrder::Id => "출시순",
+            SongOrder::Title => "제목순",
+            SongOrder::Bpm => "BPM순",
+            SongOrder::Category => "카테고리순",
+            SongOrder::NoteCount => "노트 수순",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SongOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "id" | "release" | "출시" | "출시순" => Ok(SongOrder::Id),
+            "title" | "제목" | "제목순" => Ok(SongOrder::Title),
+            "bpm" | "비피엠" => Ok(SongOrder::Bpm),
+            "category" | "pack" | "카테고리" | "팩" => Ok(SongOrder::Category),
+            "notes" | "note" | "노트" | "노트수" => Ok(SongOrder::NoteCount),
+            _ => Err(format!("Unknown sort order: {}", s)),
+        }
+    }
+}
+
+/// Name of a category or DLC and the number of songs in it.
+pub type PackCount = (String, i64);
+
+/// Restricts songs to the given categories/DLCs and to the packs a player owns.
+#[derive(Debug, Clone, Default)]
+pub struct PackFilter {
+    /// Songs whose category or DLC is one of these packs. Empty means any pack.
+    pub packs: Vec<String>,
+    /// DLCs the player owns; songs without a DLC are always playable.
+    pub owned: Option<Vec<String>>,
+}
+
+impl PackFilter {
+    pub fn matches(&self, song: &Song) -> bool {
+        let in_packs = self.packs.is_empty()
+            || self.packs.contains(&song.category)
+            || song
+                .dlc
+                .as_ref()
+                .is_some_and(|dlc| self.packs.contains(dlc));
+        let owned = match (&self.owned, &song.dlc) {
+            (Some(owned), Some(dlc)) => owned.contains(dlc),
+            _ => true,
+        };
+        in_packs && owned
+    }
+}
+
+/// Matches songs whose BPM, or any part of their BPM range, lies within the window.
+#[derive(Debug, Clone, Copy)]
+pub struct BpmRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl BpmRange {
+    pub fn matches(&self, song: &Song) -> bool {
+        let min_bpm = song.min_bpm.unwrap_or(song.max_bpm);
+        song.max_bpm >= self.min && min_bpm <= self.max
+    }
+}
+
+/// Selects charts by button mode, difficulty and level range.
+#[derive(Debug, Clone)]
+pub struct ChartFilter {
+    pub modes: Vec<ButtonMode>,
+    pub difficulties: Vec<Difficulty>,
+    pub min_level: i32,
+    pub max_level: i32,
+    pub packs: PackFilter,
+    pub bpm: Option<BpmRange>,
+}
+
+impl ChartFilter {
+    /// Matches every chart of every mode in the given level range.
+    pub fn new(min_level: i32, max_level: i32) -> Self {
+        ChartFilter {
+            modes: ButtonMode::ALL.to_vec(),
+            difficulties: Difficulty::ALL.to_vec(),
+            min_level,
+            max_level,
+            packs: PackFilter::default(),
+            bpm: None,
+        }
+    }
+
+    /// Returns the largest note count among the charts of `song` matched by this filter.
+    pub fn max_note_count(&self, song: &Song) -> Option<i32> {
+        self.matching_charts(song)
+            .into_iter()
+            .filter_map(|(mode, difficulty, _)| song.pattern(mode, difficulty)?.note_count)
+            .max()
+    }
+
+    /// Returns the charts of `song` matched by this filter.
+    pub fn matching_charts(&self, song: &Song) -> Vec<(ButtonMode, Difficulty, i32)> {
+        let mut charts = Vec::new();
+        for &mode in &self.modes {
+            for &difficulty in &self.difficulties {
+                if let Some(level) = song.level(mode, difficulty) {
+                    if level >= self.min_level && level <= self.max_level {
+                        charts.push((mode, difficulty, level));
+                    }
+                }
+            }
+        }
+        charts
+    }
+
+    /// Returns whether `song` has a matched chart and passes the pack and BPM filters.
+    pub fn matches(&self, song: &Song) -> bool {
+        !self.matching_charts(song).is_empty()
+            && self.packs.matches(song)
+            && self.bpm.is_none_or(|bpm| bpm.matches(song))
+    }
+}
+
+/// Row of the `songs` table, without the charts of the song.
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
 #[table_name = "songs"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SongRow {
+    pub id: i32,
+    pub title: String,
+    pub artist: String,
+    pub min_bpm: Option<f64>,
+    pub max_bpm: f64,
+    pub category: String,
+    pub dlc: Option<String>,
+}
+
+/// A single chart of a song, identified by its button mode and difficulty.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "patterns"]
+pub struct Pattern {
+    pub song_id: i32,
+    /// Number of buttons, see `ButtonMode::buttons`.
+    pub mode: i32,
+    /// See `Difficulty::index`.
+    pub difficulty: i32,
+    pub level: i32,
+    pub note_count: Option<i32>,
+    pub long_note_count: Option<i32>,
+    pub designer: Option<String>,
+}
+
+impl Pattern {
+    pub fn button_mode(&self) -> Option<ButtonMode> {
+        ButtonMode::from_buttons(self.mode)
+    }
+
+    pub fn chart_difficulty(&self) -> Option<Difficulty> {
+        Difficulty::from_index(self.difficulty)
+    }
+
+    pub fn has_details(&self) -> bool {
+        self.note_count.is_some() || self.long_note_count.is_some() || self.designer.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Song {
+    pub id: i32,
+    pub title: String,
+    pub artist: String,
+    pub min_bpm: Option<f64>,
+    pub max_bpm: f64,
+    pub category: String,
+    pub dlc: Option<String>,
+    pub patterns: Vec<Pattern>,
+}
+
+impl Song {
+    /// Returns the chart of the given button mode and difficulty, if it exists.
+    pub fn pattern(&self, mode: ButtonMode, difficulty: Difficulty) -> Option<&Pattern> {
+        self.patterns.iter().find(|pattern| {
+            pattern.mode == mode.buttons() && pattern.difficulty == difficulty.index()
+        })
+    }
+
+    /// Returns the level of the given chart, if the chart exists.
+    pub fn level(&self, mode: ButtonMode, difficulty: Difficulty) -> Option<i32> {
+        self.pattern(mode, difficulty).map(|pattern| pattern.level)
+    }
+
+    fn row(&self) -> SongRow {
+        SongRow {
+            id: self.id,
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            min_bpm: self.min_bpm,
+            max_bpm: self.max_bpm,
+            category: self.category.clone(),
+            dlc: self.dlc.clone(),
+        }
+    }
+
+    /// Attaches the charts of each row, keeping the order of `rows`.
+    fn with_patterns(rows: Vec<SongRow>, conn: &SqliteConnection) -> Result<Vec<Self>, Error> {
+        use super::schema::patterns::dsl::{difficulty, mode, patterns, song_id};
+
+        let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        let mut charts: HashMap<i32, Vec<Pattern>> = HashMap::new();
+        for pattern in patterns
+            .filter(song_id.eq_any(ids))
+            .order((song_id, mode, difficulty))
+            .load::<Pattern>(conn)?
+        {
+            charts.entry(pattern.song_id).or_default().push(pattern);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Song {
+                patterns: charts.remove(&row.id).unwrap_or_default(),
+                id: row.id,
+                title: row.title,
+                artist: row.artist,
+                min_bpm: row.min_bpm,
+                max_bpm: row.max_bpm,
+                category: row.category,
+                dlc: row.dlc,
+            })
+            .collect())
+    }
+
+    pub fn all(conn: &SqliteConnection) -> Result<Vec<Self>, Error> {
+        use super::schema::songs::dsl::id;
+
+        let records = song_dsl.order(id).load::<SongRow>(conn)?;
+        Self::with_patterns(records, conn)
+    }
+
+    pub fn insert(song: &Song, conn: &SqliteConnection) -> Result<(), Error> {
+        diesel::insert_into(song_dsl)
+            .values(&song.row())
+            .execute(conn)?;
+        diesel::insert_into(patterns::table)
+            .values(&song.patterns)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Overwrites an existing song and its charts, along with their note counts
+    /// and designers.
+    pub fn update(song: &Song, conn: &SqliteConnection) -> Result<(), Error> {
+        diesel::update(song_dsl.find(song.id))
+            .set(&song.row())
+            .execute(conn)?;
+
+        let existing = patterns::table
+            .filter(patterns::song_id.eq(song.id))
+            .load::<Pattern>(conn)?;
+        for pattern in &existing {
+            if !song
+                .patterns
+                .iter()
+                .any(|chart| (chart.mode, chart.difficulty) == (pattern.mode, pattern.difficulty))
+            {
+                diesel::delete(patterns::table.find((song.id, pattern.mode, pattern.difficulty)))
+                    .execute(conn)?;
+            }
+        }
+        for pattern in &song.patterns {
+            if existing
+                .iter()
+                .any(|chart| (chart.mode, chart.difficulty) == (pattern.mode, pattern.difficulty))
+            {
+                diesel::update(patterns::table.find((song.id, pattern.mode, pattern.difficulty)))
+                    .set((
+                        patterns::level.eq(pattern.level),
+                        patterns::note_count.eq(pattern.note_count),
+                        patterns::long_note_count.eq(pattern.long_note_count),
+                        patterns::designer.eq(&pattern.designer),
+                    ))
+                    .execute(conn)?;
+            } else {
+                diesel::insert_into(patterns::table)
+                    .values(pattern)
+                    .execute(conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how many records and guild scores were logged on the charts of a song.
+    pub fn player_data(id: i32, conn: &SqliteConnection) -> Result<(i64, i64), Error> {
+        Ok((
+            records::table
+                .filter(records::song_id.eq(id))
+                .count()
+                .get_result(conn)?,
+            guild_scores::table
+                .filter(guild_scores::song_id.eq(id))
+                .count()
+                .get_result(conn)?,
+        ))
+    }
+
+    /// Deletes a song along with its charts and aliases. Records and guild
+    /// scores are never deleted, so this fails while any point at the song.
+    pub fn delete(id: i32, conn: &SqliteConnection) -> Result<(), Error> {
+        diesel::delete(patterns::table.filter(patterns::song_id.eq(id))).execute(conn)?;
+        diesel::delete(song_aliases::table.filter(song_aliases::song_id.eq(id))).execute(conn)?;
+        diesel::delete(song_dsl.find(id)).execute(conn)?;
+        Ok(())
+    }
+}
+
+/// Record of the song CSV file, which keeps one `4b0`...`8b3` column per chart.
+/// The optional chart detail columns are read separately by the importer.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct SongRecord {
     pub id: i32,
     pub title: String,
     pub artist: String,
@@ -49,136 +511,443 @@ pub struct Song {
     pub eight_button_3: Option<i32>,
 }
 
-impl Song {
-    pub fn by_id(id: i32, conn: &SqliteConnection) -> Option<Self> {
-        if let Ok(record) = song_dsl.find(id).get_result::<Song>(conn) {
-            Some(record)
-        } else {
-            None
+impl From<SongRecord> for Song {
+    fn from(record: SongRecord) -> Self {
+        let levels = [
+            (4, 0, Some(record.four_button_0)),
+            (4, 1, record.four_button_1),
+            (4, 2, record.four_button_2),
+            (4, 3, record.four_button_3),
+            (5, 0, Some(record.five_button_0)),
+            (5, 1, record.five_button_1),
+            (5, 2, record.five_button_2),
+            (5, 3, record.five_button_3),
+            (6, 0, Some(record.six_button_0)),
+            (6, 1, record.six_button_1),
+            (6, 2, record.six_button_2),
+            (6, 3, record.six_button_3),
+            (8, 0, Some(record.eight_button_0)),
+            (8, 1, record.eight_button_1),
+            (8, 2, record.eight_button_2),
+            (8, 3, record.eight_button_3),
+        ];
+        let patterns = levels
+            .iter()
+            .filter_map(|&(mode, difficulty, level)| {
+                Some(Pattern {
+                    song_id: record.id,
+                    mode,
+                    difficulty,
+                    level: level?,
+                    note_count: None,
+                    long_note_count: None,
+                    designer: None,
+                })
+            })
+            .collect();
+
+        Song {
+            id: record.id,
+            title: record.title,
+            artist: record.artist,
+            min_bpm: record.min_bpm,
+            max_bpm: record.max_bpm,
+            category: record.category,
+            dlc: record.dlc,
+            patterns,
         }
     }
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "owned_packs"]
+pub struct OwnedPack {
+    pub user_id: i64,
+    pub pack: String,
+}
+
+impl OwnedPack {
+    pub fn of_user(user: i64, conn: &SqliteConnection) -> Result<Vec<String>, Error> {
+        use super::schema::owned_packs::dsl::{owned_packs, pack, user_id};
+
+        Ok(owned_packs
+            .filter(user_id.eq(user))
+            .select(pack)
+            .order(pack)
+            .load::<String>(conn)?)
+    }
+
+    /// Replaces the packs owned by `user` with `packs`.
+    pub fn set(user: i64, packs: &[String], conn: &SqliteConnection) -> Result<(), Error> {
+        use super::schema::owned_packs::dsl::{owned_packs, user_id};
 
-    pub fn by_title(title_str: &str, conn: &SqliteConnection) -> Option<Self> {
+        let records: Vec<OwnedPack> = packs
+            .iter()
+            .map(|pack| OwnedPack {
+                user_id: user,
+                pack: pack.clone(),
+            })
+            .collect();
+
+        conn.transaction::<_, Error, _>(|| {
+            diesel::delete(owned_packs.filter(user_id.eq(user))).execute(conn)?;
+            diesel::insert_into(owned_packs)
+                .values(&records)
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+}
+
+/// Best accuracy a user has logged on a chart.
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[table_name = "records"]
+pub struct Record {
+    pub user_id: i64,
+    pub song_id: i32,
+    /// Number of buttons, see `ButtonMode::buttons`.
+    pub mode: i32,
+    /// See `Difficulty::index`.
+    pub difficulty: i32,
+    /// Accuracy in percent, from 0 to 100.
+    pub rate: f64,
+    pub max_combo: bool,
+    pub cleared_at: NaiveDateTime,
+}
+
+impl Record {
+    pub fn button_mode(&self) -> Option<ButtonMode> {
+        ButtonMode::from_buttons(self.mode)
+    }
+
+    pub fn chart_difficulty(&self) -> Option<Difficulty> {
+        Difficulty::from_index(self.difficulty)
+    }
+
+    /// Returns the records of `user` on the charts of a song.
+    pub fn of_song(user: i64, song: i32, conn: &SqliteConnection) -> Result<Vec<Self>, Error> {
+        use super::schema::records::dsl::{difficulty, mode, records, song_id, user_id};
+
+        Ok(records
+            .filter(user_id.eq(user))
+            .filter(song_id.eq(song))
+            .order((mode, difficulty))
+            .load::<Record>(conn)?)
+    }
+
+    /// Returns every record of `user` in a button mode.
+    pub fn of_user_in_mode(
+        user: i64,
+        chart_mode: ButtonMode,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<Self>, Error> {
+        use super::schema::records::dsl::{mode, records, user_id};
+
+        Ok(records
+            .filter(user_id.eq(user))
+            .filter(mode.eq(chart_mode.buttons()))
+            .load::<Record>(conn)?)
+    }
+
+    /// Returns the records of `user`, highest rate first, along with the title
+    /// of their song.
+    pub fn of_user(
+        user: i64,
+        modes: &[ButtonMode],
+        offset: i64,
+        conn: &SqliteConnection,
+    ) -> Result<(i64, Vec<(Record, String)>), Error> {
+        use super::schema::records::dsl::{difficulty, mode, rate, records, song_id, user_id};
         use super::schema::songs::dsl::title;
 
-        if title_str == "" {
-            None
-        } else {
-            song_dsl
-                .filter(title.like(format!("%{}%", title_str)))
-                .first(conn)
-                .optional()
-                .expect("Error loading songs")
+        let modes: Vec<i32> = modes.iter().map(|m| m.buttons()).collect();
+        Ok((
+            records
+                .filter(user_id.eq(user))
+                .filter(mode.eq_any(modes.clone()))
+                .count()
+                .get_result(conn)?,
+            records
+                .inner_join(song_dsl)
+                .filter(user_id.eq(user))
+                .filter(mode.eq_any(modes))
+                .order((rate.desc(), song_id, mode, difficulty))
+                .select((records::all_columns(), title))
+                .limit(25)
+                .offset(offset)
+                .load::<(Record, String)>(conn)?,
+        ))
+    }
+
+    /// Checks a row of an uploaded clear list and turns it into a record of `user`.
+    pub fn from_clear(
+        user: i64,
+        clear: &ClearRecord,
+        cleared_at: NaiveDateTime,
+        conn: &SqliteConnection,
+    ) -> Result<Self, Error> {
+        use super::schema::patterns::dsl::patterns;
+
+        let chart_mode = clear.mode.parse::<ButtonMode>().map_err(Error::Invalid)?;
+        let chart_difficulty = clear
+            .difficulty
+            .parse::<Difficulty>()
+            .map_err(Error::Invalid)?;
+        if !(0.0..=100.0).contains(&clear.rate) {
+            return Err(Error::Invalid(format!("Invalid rate: {}", clear.rate)));
+        }
+        let key = (
+            clear.song_id,
+            chart_mode.buttons(),
+            chart_difficulty.index(),
+        );
+        let exists = patterns
+            .find(key)
+            .first::<Pattern>(conn)
+            .optional()?
+            .is_some();
+        if !exists {
+            return Err(Error::Invalid(format!(
+                "Song {} has no {} {} chart",
+                clear.song_id, chart_mode, chart_difficulty
+            )));
         }
+
+        Ok(Record {
+            user_id: user,
+            song_id: clear.song_id,
+            mode: chart_mode.buttons(),
+            difficulty: chart_difficulty.index(),
+            rate: clear.rate,
+            max_combo: clear.max_combo.unwrap_or(false),
+            cleared_at,
+        })
     }
 
-    pub fn by_level(
-        button: i32,
-        level: i32,
-        offset: i64,
+    /// Saves `record` unless the user already did better on the chart. Returns
+    /// the previous record when it was kept, or `None` when `record` was stored.
+    pub fn save(record: &Record, conn: &SqliteConnection) -> Result<Option<Self>, Error> {
+        use super::schema::records::dsl::records;
+
+        conn.transaction::<_, Error, _>(|| {
+            let key = (
+                record.user_id,
+                record.song_id,
+                record.mode,
+                record.difficulty,
+            );
+            match records.find(key).first::<Record>(conn).optional()? {
+                None => {
+                    diesel::insert_into(records).values(record).execute(conn)?;
+                    Ok(None)
+                }
+                Some(previous)
+                    if is_better_clear(
+                        (record.rate, record.max_combo),
+                        (previous.rate, previous.max_combo),
+                    ) =>
+                {
+                    diesel::update(records.find(key))
+                        .set(record)
+                        .execute(conn)?;
+                    Ok(None)
+                }
+                Some(previous) => Ok(Some(previous)),
+            }
+        })
+    }
+}
+
+/// Returns whether a new clear beats the previous best on the same chart, both
+/// given as rate and max combo. Only whole plays are kept, so the higher rate
+/// wins and a max combo merely breaks ties.
+fn is_better_clear(clear: (f64, bool), previous: (f64, bool)) -> bool {
+    let (rate, max_combo) = clear;
+    let (previous_rate, previous_max_combo) = previous;
+    rate > previous_rate || (rate == previous_rate && max_combo && !previous_max_combo)
+}
+
+/// Row of a clear list uploaded by a player.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ClearRecord {
+    pub song_id: i32,
+    /// Button mode such as `6B`.
+    pub mode: String,
+    /// Difficulty such as `SC`.
+    pub difficulty: String,
+    pub rate: f64,
+    #[serde(default)]
+    pub max_combo: Option<bool>,
+}
+
+/// Best rate a player submitted on a chart to the leaderboard of a guild.
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[table_name = "guild_scores"]
+pub struct GuildScore {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub song_id: i32,
+    /// Number of buttons, see `ButtonMode::buttons`.
+    pub mode: i32,
+    /// See `Difficulty::index`.
+    pub difficulty: i32,
+    /// Accuracy in percent, from 0 to 100.
+    pub rate: f64,
+    pub max_combo: bool,
+    pub submitted_at: NaiveDateTime,
+}
+
+/// Sum of the best rates of a player over every chart of a button mode.
+#[derive(Debug)]
+pub struct PlayerTotal {
+    pub user_id: i64,
+    pub total: f64,
+    pub charts: usize,
+}
+
+impl GuildScore {
+    /// Saves `score` unless the player already submitted a better one. Returns
+    /// the previous score when it was kept, or `None` when `score` was stored.
+    pub fn submit(score: &GuildScore, conn: &SqliteConnection) -> Result<Option<Self>, Error> {
+        use super::schema::guild_scores::dsl::guild_scores;
+
+        conn.transaction::<_, Error, _>(|| {
+            let key = (
+                score.guild_id,
+                score.user_id,
+                score.song_id,
+                score.mode,
+                score.difficulty,
+            );
+            match guild_scores
+                .find(key)
+                .first::<GuildScore>(conn)
+                .optional()?
+            {
+                None => {
+                    diesel::insert_into(guild_scores)
+                        .values(score)
+                        .execute(conn)?;
+                    Ok(None)
+                }
+                Some(previous)
+                    if is_better_clear(
+                        (score.rate, score.max_combo),
+                        (previous.rate, previous.max_combo),
+                    ) =>
+                {
+                    diesel::update(guild_scores.find(key))
+                        .set(score)
+                        .execute(conn)?;
+                    Ok(None)
+                }
+                Some(previous) => Ok(Some(previous)),
+            }
+        })
+    }
+
+    /// Returns the best scores of a guild on a chart. Ties go to whoever
+    /// submitted first.
+    pub fn top(
+        guild: i64,
+        song: i32,
+        chart_mode: ButtonMode,
+        chart_difficulty: Difficulty,
+        limit: i64,
         conn: &SqliteConnection,
-    ) -> (i64, Vec<Self>) {
-        use super::schema::songs::dsl::{
-            eight_button_0, eight_button_1, eight_button_2, eight_button_3, five_button_0,
-            five_button_1, five_button_2, five_button_3, four_button_0, four_button_1,
-            four_button_2, four_button_3, six_button_0, six_button_1, six_button_2, six_button_3,
+    ) -> Result<Vec<Self>, Error> {
+        use super::schema::guild_scores::dsl::{
+            difficulty, guild_id, guild_scores, mode, rate, song_id, submitted_at, user_id,
         };
 
-        match button {
-            4 => (
-                song_dsl
-                    .filter(four_button_0.eq(level))
-                    .or_filter(four_button_1.eq(level))
-                    .or_filter(four_button_2.eq(level))
-                    .or_filter(four_button_3.eq(level))
-                    .count()
-                    .get_result(conn)
-                    .expect("Error loading songs"),
-                song_dsl
-                    .filter(four_button_0.eq(level))
-                    .or_filter(four_button_1.eq(level))
-                    .or_filter(four_button_2.eq(level))
-                    .or_filter(four_button_3.eq(level))
-                    .limit(25)
-                    .offset(offset)
-                    .load::<Song>(conn)
-                    .expect("Error loading songs"),
-            ),
-            5 => (
-                song_dsl
-                    .filter(five_button_0.eq(level))
-                    .or_filter(five_button_1.eq(level))
-                    .or_filter(five_button_2.eq(level))
-                    .or_filter(five_button_3.eq(level))
-                    .count()
-                    .get_result(conn)
-                    .expect("Error loading songs"),
-                song_dsl
-                    .filter(five_button_0.eq(level))
-                    .or_filter(five_button_1.eq(level))
-                    .or_filter(five_button_2.eq(level))
-                    .or_filter(five_button_3.eq(level))
-                    .limit(25)
-                    .offset(offset)
-                    .load::<Song>(conn)
-                    .expect("Error loading songs"),
-            ),
-            6 => (
-                song_dsl
-                    .filter(six_button_0.eq(level))
-                    .or_filter(six_button_1.eq(level))
-                    .or_filter(six_button_2.eq(level))
-                    .or_filter(six_button_3.eq(level))
-                    .count()
-                    .get_result(conn)
-                    .expect("Error loading songs"),
-                song_dsl
-                    .filter(six_button_0.eq(level))
-                    .or_filter(six_button_1.eq(level))
-                    .or_filter(six_button_2.eq(level))
-                    .or_filter(six_button_3.eq(level))
-                    .limit(25)
-                    .offset(offset)
-                    .load::<Song>(conn)
-                    .expect("Error loading songs"),
-            ),
-            8 => (
-                song_dsl
-                    .filter(eight_button_0.eq(level))
-                    .or_filter(eight_button_1.eq(level))
-                    .or_filter(eight_button_2.eq(level))
-                    .or_filter(eight_button_3.eq(level))
-                    .count()
-                    .get_result(conn)
-                    .expect("Error loading songs"),
-                song_dsl
-                    .filter(eight_button_0.eq(level))
-                    .or_filter(eight_button_1.eq(level))
-                    .or_filter(eight_button_2.eq(level))
-                    .or_filter(eight_button_3.eq(level))
-                    .limit(25)
-                    .offset(offset)
-                    .load::<Song>(conn)
-                    .expect("Error loading songs"),
-            ),
-            _ => (0, vec![]),
-        }
+        Ok(guild_scores
+            .filter(guild_id.eq(guild))
+            .filter(song_id.eq(song))
+            .filter(mode.eq(chart_mode.buttons()))
+            .filter(difficulty.eq(chart_difficulty.index()))
+            .order((rate.desc(), submitted_at, user_id))
+            .limit(limit)
+            .load::<GuildScore>(conn)?)
     }
 
-    pub fn create_or_update(song: &Song, conn: &SqliteConnection) -> Option<Self> {
-        if let None = Self::by_id(song.id, conn) {
-            diesel::insert_into(song_dsl)
-                .values(song)
-                .execute(conn)
-                .expect("Error saving new song");
-        } else {
-            let target = song_dsl.find(song.id);
-            diesel::update(target)
-                .set(song)
-                .execute(conn)
-                .expect("Error saving new song");
+    /// Ranks the players of a guild by the sum of their rates in a button mode.
+    pub fn standings(
+        guild: i64,
+        chart_mode: ButtonMode,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<PlayerTotal>, Error> {
+        use super::schema::guild_scores::dsl::{guild_id, guild_scores, mode, rate, user_id};
+
+        let scores = guild_scores
+            .filter(guild_id.eq(guild))
+            .filter(mode.eq(chart_mode.buttons()))
+            .select((user_id, rate))
+            .load::<(i64, f64)>(conn)?;
+
+        let mut totals: BTreeMap<i64, PlayerTotal> = BTreeMap::new();
+        for (user, score) in scores {
+            let total = totals.entry(user).or_insert(PlayerTotal {
+                user_id: user,
+                total: 0.0,
+                charts: 0,
+            });
+            total.total += score;
+            total.charts += 1;
         }
+        let mut standings: Vec<PlayerTotal> = totals.into_values().collect();
+        standings.sort_by(|a, b| {
+            b.total
+                .partial_cmp(&a.total)
+                .unwrap_or(Ordering::Equal)
+                .then(a.user_id.cmp(&b.user_id))
+        });
+        Ok(standings)
+    }
+}
+
+/// Alternate spelling, romanization or nickname of a song title.
+#[derive(Debug, Deserialize, Queryable, Insertable)]
+#[serde(rename_all(deserialize = "camelCase"))]
+#[table_name = "song_aliases"]
+pub struct SongAlias {
+    pub song_id: i32,
+    pub alias: String,
+}
+
+impl SongAlias {
+    pub fn all(conn: &SqliteConnection) -> Result<Vec<Self>, Error> {
+        use super::schema::song_aliases::dsl::song_aliases;
+
+        Ok(song_aliases.load::<SongAlias>(conn)?)
+    }
+
+    pub fn create(alias: &SongAlias, conn: &SqliteConnection) -> Result<(), Error> {
+        use super::schema::song_aliases::dsl::song_aliases;
+
+        diesel::insert_or_ignore_into(song_aliases)
+            .values(alias)
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn better_clear_has_the_higher_rate() {
+        assert!(is_better_clear((98.5, false), (97.0, false)));
+        assert!(is_better_clear((99.0, false), (97.0, true)));
+        assert!(!is_better_clear((96.0, false), (97.0, false)));
+        assert!(!is_better_clear((95.0, true), (97.0, false)));
+    }
 
-        Self::by_id(song.id, conn)
+    #[test]
+    fn max_combo_breaks_rate_ties() {
+        assert!(is_better_clear((97.0, true), (97.0, false)));
+        assert!(!is_better_clear((97.0, false), (97.0, true)));
+        assert!(!is_better_clear((97.0, true), (97.0, true)));
     }
 }
@@ -1,3 +1,54 @@
+table! {
+    guild_scores (guild_id, user_id, song_id, mode, difficulty) {
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        song_id -> Integer,
+        mode -> Integer,
+        difficulty -> Integer,
+        rate -> Double,
+        max_combo -> Bool,
+        submitted_at -> Timestamp,
+    }
+}
+
+table! {
+    owned_packs (user_id, pack) {
+        user_id -> BigInt,
+        pack -> Text,
+    }
+}
+
+table! {
+    patterns (song_id, mode, difficulty) {
+        song_id -> Integer,
+        mode -> Integer,
+        difficulty -> Integer,
+        level -> Integer,
+        note_count -> Nullable<Integer>,
+        long_note_count -> Nullable<Integer>,
+        designer -> Nullable<Text>,
+    }
+}
+
+table! {
+    records (user_id, song_id, mode, difficulty) {
+        user_id -> BigInt,
+        song_id -> Integer,
+        mode -> Integer,
+        difficulty -> Integer,
+        rate -> Double,
+        max_combo -> Bool,
+        cleared_at -> Timestamp,
+    }
+}
+
+table! {
+    song_aliases (song_id, alias) {
+        song_id -> Integer,
+        alias -> Text,
+    }
+}
+
 table! {
     songs (id) {
         id -> Integer,
@@ -7,21 +58,19 @@ table! {
         max_bpm -> Float8,
         category -> Text,
         dlc -> Nullable<Text>,
-        four_button_0 -> Integer,
-        four_button_1 -> Nullable<Integer>,
-        four_button_2 -> Nullable<Integer>,
-        four_button_3 -> Nullable<Integer>,
-        five_button_0 -> Integer,
-        five_button_1 -> Nullable<Integer>,
-        five_button_2 -> Nullable<Integer>,
-        five_button_3 -> Nullable<Integer>,
-        six_button_0 -> Integer,
-        six_button_1 -> Nullable<Integer>,
-        six_button_2 -> Nullable<Integer>,
-        six_button_3 -> Nullable<Integer>,
-        eight_button_0 -> Integer,
-        eight_button_1 -> Nullable<Integer>,
-        eight_button_2 -> Nullable<Integer>,
-        eight_button_3 -> Nullable<Integer>,
     }
 }
+
+joinable!(guild_scores -> songs (song_id));
+joinable!(patterns -> songs (song_id));
+joinable!(records -> songs (song_id));
+joinable!(song_aliases -> songs (song_id));
+
+allow_tables_to_appear_in_same_query!(
+    guild_scores,
+    owned_packs,
+    patterns,
+    records,
+    song_aliases,
+    songs,
+);
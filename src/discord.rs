@@ -1,26 +1,56 @@
 pub mod general;
 pub mod djmax;
+pub mod leaderboard;
+pub mod records;
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::Arc;
 
 use serenity::{
     client::Client,
     framework::standard::{
         StandardFramework,
-        CommandResult,
-        macros::{
-            group,
-            command,
-        },
+        macros::group,
     },
+    model::channel::Reaction,
     prelude::*,
 };
 
+use crate::db::{establish_pool, ConnectionPool};
+use crate::catalog::Catalog;
 use crate::discord::general::PING_COMMAND;
 use crate::discord::djmax::{
+    select_candidate,
+    clear_selection,
+    turn_page,
+    ERROR_MESSAGE,
+    PendingSelections,
+    LevelPages,
     SEARCH_BY_TITLE_COMMAND,
+    SEARCH_BY_ARTIST_COMMAND,
+    SPEED_MOD_COMMAND,
+    PACKS_COMMAND,
+    OWNED_PACKS_COMMAND,
+    SEARCH_BY_BPM_COMMAND,
+    SEARCH_BY_NOTES_COMMAND,
+    RANDOM_CHART_COMMAND,
+    SEARCH_BY_LEVEL_COMMAND,
     SEARCH_BY_4B_LEVEL_COMMAND,
     SEARCH_BY_5B_LEVEL_COMMAND,
     SEARCH_BY_6B_LEVEL_COMMAND,
     SEARCH_BY_8B_LEVEL_COMMAND,
+    RELOAD_COMMAND,
+};
+use crate::discord::leaderboard::{
+    TOP_COMMAND,
+    RANK_COMMAND,
+};
+use crate::discord::records::{
+    RECORD_COMMAND,
+    RECORDS_COMMAND,
+    POWER_COMMAND,
+    RECOMMEND_COMMAND,
 };
 
 #[group]
@@ -30,20 +60,60 @@ struct General;
 #[group]
 #[prefixes("djmax", "d", "디제이맥스", "디맥")]
 #[default_command(search_by_title)]
-#[commands(search_by_title, search_by_4b_level, search_by_5b_level, search_by_6b_level, search_by_8b_level)]
+#[commands(search_by_title, search_by_artist, speed_mod, packs, owned_packs, search_by_bpm, search_by_notes, random_chart, search_by_level, search_by_4b_level, search_by_5b_level, search_by_6b_level, search_by_8b_level, reload, record, records, power, recommend, top, rank)]
 struct Djmax;
 
 struct Handler;
 
-impl EventHandler for Handler {}
+impl EventHandler for Handler {
+    fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        turn_page(&ctx, &reaction, false);
+    }
+
+    fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        turn_page(&ctx, &reaction, true);
+    }
+}
+
+pub fn establish_client(token: &String) -> Result<Client, Box<dyn Error>> {
+    let mut client = Client::new(token, Handler)?;
+    let pool = establish_pool()?;
+    {
+        let conn = pool.get()?;
+        let mut data = client.data.write();
+        data.insert::<PendingSelections>(HashMap::new());
+        data.insert::<LevelPages>(HashMap::new());
+        data.insert::<Catalog>(Arc::new(Catalog::load(&conn)?));
+        data.insert::<ConnectionPool>(pool.clone());
+    }
+
+    // the application owner is the only one allowed to run owner-only commands
+    let info = client.cache_and_http.http.get_current_application_info()?;
+    let mut owners = HashSet::new();
+    owners.insert(info.owner.id);
 
-pub fn establish_client(token: &String) -> Client {
-    let mut client = Client::new(token, Handler).expect("Err creating client");
     client.with_framework(StandardFramework::new()
-        .configure(|c| c.prefix("~")) // set the bot's prefix to "~"
+        .configure(|c| c
+            .prefix("~") // set the bot's prefix to "~"
+            .owners(owners))
+        .before(|ctx, msg, _| {
+            // a new command replaces any candidate list still waiting for a number
+            clear_selection(ctx, msg);
+            true
+        })
+        .normal_message(select_candidate)
+        .after(|ctx, msg, command_name, result| {
+            // log the failure and let the user know instead of staying silent
+            if let Err(why) = result {
+                println!("Error in {}: {:?}", command_name, why);
+                if let Err(why) = msg.channel_id.say(&ctx.http, ERROR_MESSAGE) {
+                    println!("Error sending message: {:?}", why);
+                }
+            }
+        })
         .group(&GENERAL_GROUP)
         .group(&DJMAX_GROUP)
     );
 
-    client
+    Ok(client)
 }
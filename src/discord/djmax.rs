@@ -1,19 +1,289 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use serenity::{
-    builder::CreateMessage,
+    builder::CreateEmbed,
     framework::standard::{macros::command, Args, CommandResult},
-    model::channel::Message,
+    model::{
+        channel::{Message, Reaction, ReactionType},
+        id::{ChannelId, MessageId, UserId},
+    },
     prelude::*,
 };
 
-use crate::db::{establish_connection, models::Song};
+use diesel::sqlite::SqliteConnection;
+use rand::seq::SliceRandom;
+
+use crate::catalog::Catalog;
+use crate::db::{
+    models::{
+        BpmRange, ButtonMode, ChartFilter, Difficulty, OwnedPack, PackFilter, Song, SongOrder,
+    },
+    ConnectionPool, PooledConnection,
+};
+use crate::import;
+
+/// Maximum number of candidates listed when a title search is ambiguous.
+pub(super) const MAX_CANDIDATES: usize = 10;
+
+/// Maximum number of songs `random_chart` draws at once.
+const MAX_DRAWS: usize = 10;
+
+/// Candidate lists awaiting a follow-up number, keyed by channel and requesting user.
+pub struct PendingSelections;
+
+impl TypeMapKey for PendingSelections {
+    type Value = HashMap<(ChannelId, UserId), (String, Vec<i32>, Instant)>;
+}
+
+/// How long a candidate list waits for the follow-up number.
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of kept songs listed after a reload.
+const MAX_KEPT_SONGS: usize = 10;
+
+// Reactions that turn the pages of a level search result.
+const PREVIOUS_PAGE: &str = "◀";
+const NEXT_PAGE: &str = "▶";
+
+/// How long a level search result keeps responding to page reactions.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Paging state of a level search result.
+#[derive(Clone)]
+pub struct LevelPager {
+    user: UserId,
+    filter: ChartFilter,
+    order: SongOrder,
+    page: i64,
+    count: i64,
+    started: Instant,
+    /// Reactions the bot is removing, whose removal events must not turn the page.
+    deleting: u32,
+}
+
+/// Level search results that can be paged with reactions, keyed by message.
+pub struct LevelPages;
+
+impl TypeMapKey for LevelPages {
+    type Value = HashMap<MessageId, LevelPager>;
+}
+
+// In-game speed multipliers range from 1.00 to 5.00 in steps of 0.25.
+const MIN_SPEED: f64 = 1.0;
+const MAX_SPEED: f64 = 5.0;
+const SPEED_STEP: f64 = 0.25;
+
+/// Returns the pair of speed multipliers whose absolute BPM brackets `target`.
+fn bracket_speeds(bpm: f64, target: f64) -> (f64, f64) {
+    let steps = (target / bpm / SPEED_STEP).floor();
+    let lower = (steps * SPEED_STEP).clamp(MIN_SPEED, MAX_SPEED - SPEED_STEP);
+    (lower, lower + SPEED_STEP)
+}
+
+/// Returns whether some in-game speed multiplier reaches `target` from `bpm`.
+fn in_speed_range(bpm: f64, target: f64) -> bool {
+    (bpm * MIN_SPEED..=bpm * MAX_SPEED).contains(&target)
+}
+
+fn format_speeds(bpm: f64, target: f64) -> String {
+    let (lower, upper) = bracket_speeds(bpm, target);
+    let mut text = format!(
+        "{} * {:.2} = {}, {} * {:.2} = {}",
+        bpm,
+        lower,
+        bpm * lower,
+        bpm,
+        upper,
+        bpm * upper
+    );
+    if !in_speed_range(bpm, target) {
+        text.push_str(&format!(
+            "\n목표 BPM이 게임의 배속 범위({:.2}~{:.2}배)를 벗어나 가장 가까운 배속을 표시합니다.",
+            MIN_SPEED, MAX_SPEED
+        ));
+    }
+    text
+}
+
+/// Renders the levels of every chart as a monospaced 4B/5B/6B/8B x NM/HD/MX/SC grid.
+fn pattern_table(song: &Song) -> String {
+    let mut table = String::from("```\n     NM  HD  MX  SC\n");
+    for &mode in &ButtonMode::ALL {
+        table.push_str(&format!("{} ", mode));
+        for &difficulty in &Difficulty::ALL {
+            match song.level(mode, difficulty) {
+                Some(level) => table.push_str(&format!("{:>4}", level)),
+                None => table.push_str(&format!("{:>4}", "-")),
+            }
+        }
+        table.push('\n');
+    }
+    table.push_str("```");
+    table
+}
+
+/// Normalizes a pack name so that `technika3` matches "TECHNIKA 3".
+fn normalize_pack(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && !['_', '-'].contains(c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns the category or DLC name written as `name`, if any.
+fn resolve_pack(name: &str, catalog: &Catalog) -> Option<String> {
+    let name = normalize_pack(name);
+    let (categories, dlcs) = catalog.pack_counts();
+    categories
+        .into_iter()
+        .chain(dlcs)
+        .map(|(pack, _)| pack)
+        .find(|pack| normalize_pack(pack) == name)
+}
+
+/// Consumes a `pack:(팩 이름)` or `owned` search token into `filter`.
+/// Returns `Ok(false)` when `arg` is not such a token.
+fn parse_pack_token(
+    arg: &str,
+    msg: &Message,
+    filter: &mut PackFilter,
+    catalog: &Catalog,
+    conn: &SqliteConnection,
+) -> Result<bool, String> {
+    if arg.eq_ignore_ascii_case("owned") || arg == "보유" {
+        let owned = OwnedPack::of_user(msg.author.id.0 as i64, conn).map_err(|why| {
+            println!("Error loading owned packs: {}", why);
+            String::from(ERROR_MESSAGE)
+        })?;
+        filter.owned = Some(owned);
+        return Ok(true);
+    }
+
+    let lower = arg.to_lowercase();
+    let name = match lower
+        .strip_prefix("pack:")
+        .or_else(|| lower.strip_prefix("팩:"))
+    {
+        Some(name) => name,
+        None => return Ok(false),
+    };
+    match resolve_pack(name, catalog) {
+        Some(pack) => {
+            filter.packs.push(pack);
+            Ok(true)
+        }
+        None => Err(format!(
+            "`{}` 팩을 찾을 수 없습니다. `packs` 명령어로 팩 목록을 확인해 주세요.",
+            name
+        )),
+    }
+}
+
+/// Separates pack tokens from the words of a free-text query.
+fn split_pack_tokens(
+    text: &str,
+    msg: &Message,
+    catalog: &Catalog,
+    conn: &SqliteConnection,
+) -> Result<(PackFilter, String), String> {
+    let mut packs = PackFilter::default();
+    let mut words = Vec::new();
+    for word in text.split_whitespace() {
+        if !parse_pack_token(word, msg, &mut packs, catalog, conn)? {
+            words.push(word);
+        }
+    }
+    Ok((packs, words.join(" ")))
+}
+
+/// Lists the note counts and designers of the charts that have them.
+fn pattern_details(song: &Song) -> String {
+    let mut details = String::new();
+    for pattern in song.patterns.iter().filter(|pattern| pattern.has_details()) {
+        let (mode, difficulty) = match (pattern.button_mode(), pattern.chart_difficulty()) {
+            (Some(mode), Some(difficulty)) => (mode, difficulty),
+            _ => continue,
+        };
+        details.push_str(&format!("{} {} {}", mode, difficulty, pattern.level));
+        if let Some(note_count) = pattern.note_count {
+            details.push_str(&format!(" · {} notes", note_count));
+        }
+        if let Some(long_note_count) = pattern.long_note_count {
+            details.push_str(&format!(" (LN {})", long_note_count));
+        }
+        if let Some(designer) = &pattern.designer {
+            details.push_str(&format!(" · {}", designer));
+        }
+        details.push('\n');
+    }
+    details
+}
+
+/// Reply sent when a command fails, e.g. because the database is unavailable.
+pub(crate) const ERROR_MESSAGE: &str =
+    "요청을 처리하는 중 오류가 발생했습니다. 잠시 후 다시 시도해 주세요.";
+
+/// Borrows a connection from the pool stored in `Context::data`.
+pub(crate) fn connection(ctx: &Context) -> Result<PooledConnection, String> {
+    let pool = ctx
+        .data
+        .read()
+        .get::<ConnectionPool>()
+        .cloned()
+        .ok_or_else(|| String::from("Connection pool is not initialized"))?;
+    pool.get()
+        .map_err(|why| format!("Error getting a database connection: {}", why))
+}
 
-fn send_simple_message(ctx: &mut Context, msg: &Message, text: &str) -> CommandResult {
+/// Shares the song catalog stored in `Context::data`.
+pub(crate) fn catalog(ctx: &Context) -> Result<Arc<Catalog>, String> {
+    ctx.data
+        .read()
+        .get::<Catalog>()
+        .cloned()
+        .ok_or_else(|| String::from("Song catalog is not loaded"))
+}
+
+pub(crate) fn send_simple_message(ctx: &mut Context, msg: &Message, text: &str) -> CommandResult {
     if let Err(why) = msg.channel_id.say(&ctx.http, &text) {
         println!("Error sending message: {:?}", why);
     }
     Ok(())
 }
 
+fn send_song(ctx: &mut Context, msg: &Message, query: &str, content: &Song) -> CommandResult {
+    // For debug purpose
+    #[cfg(debug_assertions)]
+    println!("{:?}", content);
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(format!("\"{}\" 검색 결과:", query));
+        m.embed(|e| {
+            e.title(content.title.as_str());
+            e.description(content.artist.as_str());
+            e.field("BPM", format_bpm(content), true);
+            e.field("Category", &content.category, true);
+            if let Some(dlc) = &content.dlc {
+                e.field("DLC", dlc, true);
+            }
+            e.field("Patterns", pattern_table(content), false);
+            let details = pattern_details(content);
+            if !details.is_empty() {
+                e.field("Charts", details, false);
+            }
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
 #[command]
 #[aliases("search", "st", "s", "곡검색")]
 #[bucket = "djmax"]
@@ -26,140 +296,861 @@ pub fn search_by_title(ctx: &mut Context, msg: &Message, args: Args) -> CommandR
         );
     }
 
-    let conn = establish_connection();
-    let original_query = str::replace(args.message(), "%", "");
-    let query = str::replace(original_query.as_str(), " ", "%");
-    let contents = Song::by_title(query.as_str(), &conn);
-
-    if let Some(content) = contents {
-        // For debug purpose
-        #[cfg(debug_assertions)]
-        println!("{:?}", content);
-
-        if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
-            m.content(format!("\"{}\" 검색 결과:", original_query.as_str()));
-            m.embed(|e| {
-                e.title(content.title.as_str());
-                e.description(content.artist.as_str());
-                let mut bpm_string = String::new();
-                if let Some(bpm) = content.min_bpm {
-                    bpm_string.push_str(format!("{}~", bpm).as_str());
-                }
-                bpm_string.push_str(format!("{}", content.max_bpm).as_str());
-                e.field("BPM", bpm_string, true);
-                e.field("Category", content.category, true);
-                if let Some(dlc) = content.dlc {
-                    e.field("DLC", dlc, true);
-                }
-                e
-            });
-            m
-        }) {
-            println!("Error sending message: {:?}", why);
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let (packs, query) = match split_pack_tokens(args.message(), msg, &catalog, &conn) {
+        Ok(split) => split,
+        Err(why) => return send_simple_message(ctx, msg, why.as_str()),
+    };
+    if query.is_empty() {
+        return send_simple_message(
+            ctx,
+            msg,
+            "잘못된 사용법입니다.\n사용법: `search_by_title (곡 제목의 일부)`",
+        );
+    }
+
+    let original_query = str::replace(query.as_str(), "%", "");
+    let mut contents = catalog.search_title(original_query.as_str(), &packs);
+
+    match contents.len() {
+        0 => {
+            let artist_contents = catalog.by_artist(original_query.as_str(), &packs);
+            if !artist_contents.is_empty() {
+                return send_artist_songs(ctx, msg, original_query.as_str(), artist_contents);
+            }
+
+            let suggestions = catalog.suggest(original_query.as_str(), &packs);
+            if suggestions.is_empty() {
+                return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
+            }
+            send_candidates(
+                ctx,
+                msg,
+                original_query,
+                "검색 결과가 없습니다. 혹시 다음 곡을 찾으셨나요? 원하는 곡의 번호를 입력해 주세요.",
+                suggestions,
+            )
         }
+        1 => send_song(ctx, msg, original_query.as_str(), &contents[0]),
+        _ => {
+            contents.truncate(MAX_CANDIDATES);
+            send_candidates(
+                ctx,
+                msg,
+                original_query,
+                "검색 결과가 여러 곡입니다. 원하는 곡의 번호를 입력해 주세요.",
+                contents,
+            )
+        }
+    }
+}
 
-        Ok(())
-    } else {
-        send_simple_message(ctx, msg, "검색 결과가 없습니다.")
+/// Lists `contents` as numbered candidates and waits for the user to pick one.
+fn send_candidates(
+    ctx: &mut Context,
+    msg: &Message,
+    query: String,
+    header: &str,
+    contents: Vec<Song>,
+) -> CommandResult {
+    let mut list = String::new();
+    for (i, content) in contents.iter().enumerate() {
+        list.push_str(&format!(
+            "`{}.` {} - {}\n",
+            i + 1,
+            content.title,
+            content.artist
+        ));
+    }
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(format!("\"{}\" {}", query.as_str(), header));
+        m.embed(|e| {
+            e.description(list);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    let candidates = contents.iter().map(|content| content.id).collect();
+    let mut data = ctx.data.write();
+    if let Some(pending) = data.get_mut::<PendingSelections>() {
+        // Forget the lists nobody answered, so the map does not grow forever.
+        pending.retain(|_, (_, _, started)| started.elapsed() < SELECTION_TIMEOUT);
+        pending.insert(
+            (msg.channel_id, msg.author.id),
+            (query, candidates, Instant::now()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Drops the candidate list of the author, who moved on to another command.
+pub fn clear_selection(ctx: &mut Context, msg: &Message) {
+    let mut data = ctx.data.write();
+    if let Some(pending) = data.get_mut::<PendingSelections>() {
+        pending.remove(&(msg.channel_id, msg.author.id));
     }
 }
 
+/// Handles a follow-up number sent after `search_by_title` listed several candidates.
+pub fn select_candidate(ctx: &mut Context, msg: &Message) {
+    let choice = match msg.content.trim().parse::<usize>() {
+        Ok(choice) if choice >= 1 => choice,
+        _ => return,
+    };
+
+    let key = (msg.channel_id, msg.author.id);
+    let selected = {
+        let mut data = ctx.data.write();
+        let pending = match data.get_mut::<PendingSelections>() {
+            Some(pending) => pending,
+            None => return,
+        };
+        match pending.get(&key) {
+            Some((_, _, started)) if started.elapsed() >= SELECTION_TIMEOUT => {
+                pending.remove(&key);
+                None
+            }
+            Some((_, candidates, _)) if choice <= candidates.len() => pending
+                .remove(&key)
+                .map(|(query, candidates, _)| (query, candidates[choice - 1])),
+            _ => None,
+        }
+    };
+
+    if let Some((query, song_id)) = selected {
+        let catalog = match catalog(ctx) {
+            Ok(catalog) => catalog,
+            Err(why) => {
+                println!("{}", why);
+                let _ = send_simple_message(ctx, msg, ERROR_MESSAGE);
+                return;
+            }
+        };
+        if let Some(content) = catalog.song(song_id) {
+            let _ = send_song(ctx, msg, query.as_str(), content);
+        }
+    }
+}
+
+fn send_artist_songs(
+    ctx: &mut Context,
+    msg: &Message,
+    query: &str,
+    contents: Vec<Song>,
+) -> CommandResult {
+    // Group the songs by artist, keeping the order returned by the query.
+    let mut artists: Vec<(String, String)> = Vec::new();
+    for content in &contents {
+        let line = format!("{} ({})\n", content.title, content.category);
+        match artists.last_mut() {
+            Some((artist, songs)) if *artist == content.artist => songs.push_str(&line),
+            _ => artists.push((content.artist.clone(), line)),
+        }
+    }
+    artists.truncate(25);
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(format!("\"{}\" 아티스트 검색 결과:", query));
+        m.embed(|e| {
+            e.description(format!("총 {}곡이 발견되었습니다.", contents.len()));
+            e.fields(
+                artists
+                    .into_iter()
+                    .map(|(artist, songs)| (artist, songs, false)),
+            );
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
 #[command]
-#[aliases(
-    "4blv",
-    "4lv",
-    "4l",
-    "4",
-    "4버튼레벨",
-    "4키레벨",
-    "4버튼렙",
-    "4키렙",
-    "4버튼",
-    "4키"
-)]
+#[aliases("artist", "a", "아티스트", "작곡가")]
+#[bucket = "djmax"]
+pub fn search_by_artist(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let (packs, query) = match split_pack_tokens(args.message(), msg, &catalog, &conn) {
+        Ok(split) => split,
+        Err(why) => return send_simple_message(ctx, msg, why.as_str()),
+    };
+    if query.is_empty() {
+        return send_simple_message(
+            ctx,
+            msg,
+            "잘못된 사용법입니다.\n사용법: `search_by_artist (아티스트 이름의 일부)`",
+        );
+    }
+
+    let contents = catalog.by_artist(query.as_str(), &packs);
+    if contents.is_empty() {
+        return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
+    }
+    send_artist_songs(ctx, msg, query.as_str(), contents)
+}
+
+#[command]
+#[aliases("speed", "sp", "배속", "배속설정")]
 #[bucket = "djmax"]
-pub fn search_by_4b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.len() < 1 || args.len() > 2 {
+pub fn speed_mod(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    if args.len() < 2 {
+        return send_simple_message(
+            ctx,
+            msg,
+            "잘못된 사용법입니다.\n사용법: `speed_mod (원하는 절대 BPM) (곡 제목의 일부)`",
+        );
+    }
+    let target = match args.single::<f64>() {
+        Ok(target) if target > 0.0 => target,
+        _ => {
+            return send_simple_message(
+                ctx,
+                msg,
+                "잘못된 사용법입니다.\n절대 BPM은 0보다 큰 수여야 합니다.",
+            );
+        }
+    };
+
+    let catalog = catalog(ctx)?;
+    let original_query = str::replace(args.rest(), "%", "");
+    let content = match catalog
+        .search_title(original_query.as_str(), &PackFilter::default())
+        .into_iter()
+        .next()
+    {
+        Some(content) => content,
+        None => return send_simple_message(ctx, msg, "검색 결과가 없습니다."),
+    };
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(format!("\"{}\" 배속 계산 결과:", original_query.as_str()));
+        m.embed(|e| {
+            e.title(content.title.as_str());
+            e.description(format!("목표 절대 BPM: {}", target));
+            if let Some(min_bpm) = content.min_bpm {
+                e.field("최저 BPM", format_speeds(min_bpm, target), false);
+                e.field("최고 BPM", format_speeds(content.max_bpm, target), false);
+            } else {
+                e.field("BPM", format_speeds(content.max_bpm, target), false);
+            }
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+/// Parses `12` or `12-14` (also `12~14`) into an inclusive range.
+fn parse_range<T: FromStr + Copy>(arg: &str) -> Option<(T, T)> {
+    let mut bounds = arg.splitn(2, ['-', '~']);
+    let min = bounds.next()?.trim().parse::<T>().ok()?;
+    let max = match bounds.next() {
+        Some(max) => max.trim().parse::<T>().ok()?,
+        None => min,
+    };
+    Some((min, max))
+}
+
+/// Parses `170` or `150-180` into a BPM window.
+fn parse_bpm_range(arg: &str) -> Option<BpmRange> {
+    match parse_range::<f64>(arg) {
+        Some((min, max)) if 0.0 < min && min <= max => Some(BpmRange { min, max }),
+        _ => None,
+    }
+}
+
+fn format_bpm(song: &Song) -> String {
+    match song.min_bpm {
+        Some(min_bpm) => format!("{}~{}", min_bpm, song.max_bpm),
+        None => format!("{}", song.max_bpm),
+    }
+}
+
+/// Consumes a difficulty, `pack:`, `owned` or `bpm:` search token.
+/// Returns `Ok(false)` when `arg` is none of them.
+fn parse_filter_token(
+    arg: &str,
+    msg: &Message,
+    filter: &mut ChartFilter,
+    difficulties: &mut Vec<Difficulty>,
+    catalog: &Catalog,
+    conn: &SqliteConnection,
+) -> Result<bool, String> {
+    if parse_pack_token(arg, msg, &mut filter.packs, catalog, conn)? {
+        return Ok(true);
+    }
+    if let Some(bpm) = arg.to_lowercase().strip_prefix("bpm:") {
+        return match parse_bpm_range(bpm) {
+            Some(bpm) => {
+                filter.bpm = Some(bpm);
+                Ok(true)
+            }
+            None => Err(String::from(
+                "잘못된 사용법입니다.\nBPM은 0보다 큰 수 또는 `150-180`과 같은 범위여야 합니다.",
+            )),
+        };
+    }
+    if let Ok(difficulty) = arg.parse::<Difficulty>() {
+        difficulties.push(difficulty);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn describe_filter(filter: &ChartFilter) -> String {
+    let mut description = String::new();
+    if filter.modes.len() == ButtonMode::ALL.len() {
+        description.push_str("전체 버튼 ");
+    } else {
+        for mode in &filter.modes {
+            description.push_str(&format!("{}버튼 ", mode.buttons()));
+        }
+    }
+    if filter.min_level == filter.max_level {
+        description.push_str(&format!("{}레벨", filter.min_level));
+    } else {
+        description.push_str(&format!("{}~{}레벨", filter.min_level, filter.max_level));
+    }
+    if filter.difficulties.len() != Difficulty::ALL.len() {
+        for difficulty in &filter.difficulties {
+            description.push_str(&format!(" {}", difficulty));
+        }
+    }
+    if let Some(bpm) = filter.bpm {
+        description.push_str(&format!(" (BPM {}~{})", bpm.min, bpm.max));
+    }
+    description
+}
+
+/// Number of 25-song pages needed to show `count` results.
+pub(crate) fn page_count(count: i64) -> i64 {
+    (count + 24) / 25
+}
+
+/// Offset of the first result on `page`, saturating on absurdly large pages.
+pub(crate) fn page_offset(page: i64) -> i64 {
+    (page - 1).saturating_mul(25)
+}
+
+/// Message telling the user which pages exist when `page` is past the last one.
+pub(crate) fn page_out_of_range(page: i64, count: i64) -> Option<String> {
+    if page > page_count(count) {
+        Some(format!(
+            "잘못된 사용법입니다.\n페이지는 1 이상 {} 이하의 정수여야 합니다.",
+            page_count(count)
+        ))
+    } else {
+        None
+    }
+}
+
+fn level_embed<'a>(
+    e: &'a mut CreateEmbed,
+    filter: &ChartFilter,
+    order: SongOrder,
+    page: i64,
+    count: i64,
+    contents: Vec<Song>,
+) -> &'a mut CreateEmbed {
+    let show_mode = filter.modes.len() > 1;
+    e.title(format!("{} 검색 결과:", describe_filter(filter)));
+    e.description(format!(
+        "총 {}곡이 발견되었습니다. ({} / {} 페이지, {})",
+        count,
+        page,
+        page_count(count),
+        order
+    ));
+    let mut fields = Vec::new();
+    for content in contents {
+        let mut pattern = String::new();
+        for (mode, difficulty, level) in filter.matching_charts(&content) {
+            if show_mode {
+                pattern.push_str(&format!("{} ", mode));
+            }
+            pattern.push_str(&format!("{} {}\n", difficulty, level));
+        }
+        match order {
+            SongOrder::Bpm => pattern.push_str(&format_bpm(&content)),
+            SongOrder::Category => pattern.push_str(&content.category),
+            SongOrder::NoteCount => {
+                if let Some(note_count) = filter.max_note_count(&content) {
+                    pattern.push_str(&format!("{} notes", note_count));
+                }
+            }
+            SongOrder::Id | SongOrder::Title => {}
+        }
+        fields.push((content.title, pattern, true));
+    }
+    e.fields(fields);
+    e
+}
+
+fn search_level(
+    ctx: &mut Context,
+    msg: &Message,
+    modes: Vec<ButtonMode>,
+    mut args: Args,
+) -> CommandResult {
+    if args.is_empty() {
         return send_simple_message(
             ctx,
             msg,
-            "잘못된 사용법입니다.\n사용법: `search_by_4b_level 레벨 (페이지)`",
+            "잘못된 사용법입니다.\n사용법: `search_by_level 버튼 레벨(-레벨) (난이도) (pack:팩 이름) (owned) (bpm:BPM(-BPM)) (sort:정렬 기준) (페이지)`",
         );
     }
-    let arg = args.single::<i32>();
-    if let Err(e) = arg {
-        return send_simple_message(
+    let (min_level, max_level) = match parse_range(args.single::<String>()?.as_str()) {
+        Some((min, max)) if 1 <= min && min <= max && max <= 15 => (min, max),
+        _ => {
+            return send_simple_message(
+                ctx,
+                msg,
+                "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수 또는 `12-14`와 같은 범위여야 합니다.",
+            );
+        }
+    };
+
+    let mut filter = ChartFilter::new(min_level, max_level);
+    filter.modes = modes;
+    let mut difficulties = Vec::new();
+    let mut order = SongOrder::default();
+    let mut page: i64 = 1;
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        let lower = arg.to_lowercase();
+        if let Some(key) = lower
+            .strip_prefix("sort:")
+            .or_else(|| lower.strip_prefix("정렬:"))
+        {
+            order = match key.parse::<SongOrder>() {
+                Ok(order) => order,
+                Err(_) => {
+                    return send_simple_message(
+                        ctx,
+                        msg,
+                        "잘못된 사용법입니다.\n정렬 기준은 id, title, bpm, category, notes 중 하나여야 합니다.",
+                    );
+                }
+            };
+            continue;
+        }
+        match parse_filter_token(
+            arg.as_str(),
+            msg,
+            &mut filter,
+            &mut difficulties,
+            &catalog,
+            &conn,
+        ) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(why) => return send_simple_message(ctx, msg, why.as_str()),
+        }
+        page = match arg.parse::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n난이도는 NM, HD, MX, SC 중 하나, 페이지는 1 이상의 정수여야 합니다.",
+                );
+            }
+        };
+    }
+    if !difficulties.is_empty() {
+        filter.difficulties = difficulties;
+    }
+
+    let (count, contents) = catalog.by_level(&filter, order, page_offset(page));
+    if count == 0 {
+        return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
+    }
+    if let Some(why) = page_out_of_range(page, count) {
+        return send_simple_message(ctx, msg, &why);
+    }
+
+    // For debug purpose
+    #[cfg(debug_assertions)]
+    for song in &contents {
+        println!("{:?}", song);
+    }
+
+    let sent = match msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| level_embed(e, &filter, order, page, count, contents))
+    }) {
+        Ok(sent) => sent,
+        Err(why) => {
+            println!("Error sending message: {:?}", why);
+            return Ok(());
+        }
+    };
+
+    if page_count(count) > 1 {
+        for arrow in &[PREVIOUS_PAGE, NEXT_PAGE] {
+            if let Err(why) = sent.react(&ctx, *arrow) {
+                println!("Error adding reaction: {:?}", why);
+            }
+        }
+        let mut data = ctx.data.write();
+        if let Some(pages) = data.get_mut::<LevelPages>() {
+            pages.retain(|_, pager| pager.started.elapsed() < PAGE_TIMEOUT);
+            pages.insert(
+                sent.id,
+                LevelPager {
+                    user: msg.author.id,
+                    filter,
+                    order,
+                    page,
+                    count,
+                    started: Instant::now(),
+                    deleting: 0,
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves a level search result to the previous or next page when its requester
+/// adds or removes an arrow reaction. Removals count too, because the bot can't
+/// always delete reactions for the user to press the same arrow again.
+pub fn turn_page(ctx: &Context, reaction: &Reaction, removed: bool) {
+    let step = match &reaction.emoji {
+        ReactionType::Unicode(emoji) if emoji == PREVIOUS_PAGE => -1,
+        ReactionType::Unicode(emoji) if emoji == NEXT_PAGE => 1,
+        _ => return,
+    };
+
+    let pager = {
+        let mut data = ctx.data.write();
+        let pages = match data.get_mut::<LevelPages>() {
+            Some(pages) => pages,
+            None => return,
+        };
+        let pager = match pages.get_mut(&reaction.message_id) {
+            Some(pager) => pager,
+            None => return,
+        };
+        if pager.user != reaction.user_id {
+            return;
+        }
+        if pager.started.elapsed() >= PAGE_TIMEOUT {
+            pages.remove(&reaction.message_id);
+            return;
+        }
+        if removed && pager.deleting > 0 {
+            pager.deleting -= 1;
+            return;
+        }
+        let page = pager.page + step;
+        if page < 1 || page > page_count(pager.count) {
+            return;
+        }
+        pager.page = page;
+        if !removed {
+            pager.deleting += 1;
+        }
+        pager.clone()
+    };
+
+    // Lets the user press the same arrow again; fails without Manage Messages,
+    // in which case the user's own removal turns the page instead.
+    if !removed && reaction.delete(ctx).is_err() {
+        let mut data = ctx.data.write();
+        if let Some(pager) = data
+            .get_mut::<LevelPages>()
+            .and_then(|pages| pages.get_mut(&reaction.message_id))
+        {
+            pager.deleting = pager.deleting.saturating_sub(1);
+        }
+    }
+
+    let catalog = match catalog(ctx) {
+        Ok(catalog) => catalog,
+        Err(why) => {
+            println!("{}", why);
+            return;
+        }
+    };
+    let (count, contents) = catalog.by_level(&pager.filter, pager.order, page_offset(pager.page));
+    if let Err(why) = reaction
+        .channel_id
+        .edit_message(&ctx.http, reaction.message_id, |m| {
+            m.embed(|e| level_embed(e, &pager.filter, pager.order, pager.page, count, contents))
+        })
+    {
+        println!("Error editing message: {:?}", why);
+    }
+}
+
+fn send_pack_songs(
+    ctx: &mut Context,
+    msg: &Message,
+    name: &str,
+    catalog: &Catalog,
+) -> CommandResult {
+    let pack = match resolve_pack(name, catalog) {
+        Some(pack) => pack,
+        None => {
+            return send_simple_message(
+                ctx,
+                msg,
+                &format!(
+                    "`{}` 팩을 찾을 수 없습니다. `packs` 명령어로 팩 목록을 확인해 주세요.",
+                    name
+                ),
+            );
+        }
+    };
+
+    let contents = catalog.by_pack(pack.as_str());
+
+    // Embed descriptions are limited to 2048 characters.
+    let mut list = String::new();
+    for content in &contents {
+        let line = format!("{} - {}\n", content.title, content.artist);
+        if list.chars().count() + line.chars().count() > 2000 {
+            list.push_str("...");
+            break;
+        }
+        list.push_str(&line);
+    }
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{} 수록곡 ({}곡):", pack, contents.len()));
+            e.description(list);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("새로고침")]
+#[owners_only]
+pub fn reload(ctx: &mut Context, msg: &Message) -> CommandResult {
+    let conn = connection(ctx)?;
+    let diff = match import::import_csv(&conn) {
+        Ok(diff) => diff,
+        Err(why) => {
+            println!("Error reloading songs: {}", why);
+            return send_simple_message(
+                ctx,
+                msg,
+                &format!("곡 목록을 다시 읽지 못했습니다: {}", why),
+            );
+        }
+    };
+    print!("{}", diff);
+
+    // Commands already running keep the old catalog until they finish.
+    let catalog = Catalog::load(&conn)?;
+    let count = catalog.song_count();
+    ctx.data.write().insert::<Catalog>(Arc::new(catalog));
+
+    let mut reply = format!(
+        "곡 목록을 다시 불러왔습니다. ({}곡)\n추가 {}곡, 변경 {}곡, 삭제 {}곡",
+        count,
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len()
+    );
+    if !diff.kept.is_empty() {
+        reply.push_str(&format!(
+            "\nCSV에 없지만 기록이 남아 있어 삭제하지 않은 곡 {}곡:",
+            diff.kept.len()
+        ));
+        for kept in diff.kept.iter().take(MAX_KEPT_SONGS) {
+            reply.push_str(&format!(
+                "\n{} (기록 {}개, 길드 점수 {}개)",
+                kept.song.title, kept.records, kept.scores
+            ));
+        }
+        if diff.kept.len() > MAX_KEPT_SONGS {
+            reply.push_str("\n...");
+        }
+    }
+    if !diff.skipped_aliases.is_empty() {
+        reply.push_str(&format!(
+            "\n곡이 없어 가져오지 않은 별칭 {}개",
+            diff.skipped_aliases.len()
+        ));
+    }
+    send_simple_message(ctx, msg, &reply)
+}
+
+#[command]
+#[aliases("pack", "dlc", "팩", "팩목록")]
+#[bucket = "djmax"]
+pub fn packs(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    let catalog = catalog(ctx)?;
+    if !args.is_empty() {
+        return send_pack_songs(ctx, msg, args.rest(), &catalog);
+    }
+    let (categories, dlcs) = catalog.pack_counts();
+
+    let format_counts = |counts: Vec<(String, i64)>| {
+        counts
+            .into_iter()
+            .map(|(pack, count)| format!("{} ({}곡)", pack, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("팩 목록:");
+            e.description("검색 시 `pack:(팩 이름)`으로 특정 팩의 곡만 볼 수 있습니다.");
+            e.field("Category", format_counts(categories), true);
+            e.field("DLC", format_counts(dlcs), true);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("owned", "보유팩")]
+#[bucket = "djmax"]
+pub fn owned_packs(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let user = msg.author.id.0 as i64;
+
+    let rest = args.rest().trim();
+    if rest == "none" || rest == "없음" {
+        OwnedPack::set(user, &[], &conn)?;
+    } else if !rest.is_empty() {
+        let mut packs = Vec::new();
+        for name in rest
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match resolve_pack(name, &catalog) {
+                Some(pack) => packs.push(pack),
+                None => {
+                    return send_simple_message(
+                        ctx,
+                        msg,
+                        &format!(
+                            "`{}` 팩을 찾을 수 없습니다. `packs` 명령어로 팩 목록을 확인해 주세요.",
+                            name
+                        ),
+                    );
+                }
+            }
+        }
+        OwnedPack::set(user, &packs, &conn)?;
+    }
+
+    let owned = OwnedPack::of_user(user, &conn)?;
+    if owned.is_empty() {
+        send_simple_message(
             ctx,
             msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.`",
-        );
+            "보유한 DLC가 없습니다.\n사용법: `owned_packs (팩 이름), (팩 이름), ...` 또는 `owned_packs none`",
+        )
+    } else {
+        send_simple_message(ctx, msg, &format!("보유한 DLC: {}", owned.join(", ")))
     }
-    let level = arg.unwrap();
-    if level > 15 || level < 1 {
+}
+
+#[command]
+#[aliases("bpm", "b", "비피엠")]
+#[bucket = "djmax"]
+pub fn search_by_bpm(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    if args.is_empty() {
         return send_simple_message(
             ctx,
             msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.",
+            "잘못된 사용법입니다.\n사용법: `search_by_bpm BPM(-BPM) (pack:팩 이름) (owned) (페이지)`",
         );
     }
-
-    let mut page: i64 = 1;
-    if !args.is_empty() {
-        let arg = args.single::<i64>();
-        if let Err(e) = arg {
+    let bpm = match parse_bpm_range(args.single::<String>()?.as_str()) {
+        Some(bpm) => bpm,
+        None => {
             return send_simple_message(
                 ctx,
                 msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.`",
+                "잘못된 사용법입니다.\nBPM은 0보다 큰 수 또는 `150-180`과 같은 범위여야 합니다.",
             );
         }
-        page = arg.unwrap();
-        if page <= 0 {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.",
-            );
+    };
+
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let mut packs = PackFilter::default();
+    let mut page: i64 = 1;
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        match parse_pack_token(arg.as_str(), msg, &mut packs, &catalog, &conn) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(why) => return send_simple_message(ctx, msg, why.as_str()),
         }
+        page = match arg.parse::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.",
+                );
+            }
+        };
     }
 
-    let conn = establish_connection();
-    let (count, contents) = Song::by_level(4, level, 25 * (page - 1), &conn);
+    let (count, contents) = catalog.by_bpm(bpm, &packs, page_offset(page));
     if count == 0 {
         return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
     }
-
-    // For debug purpose
-    #[cfg(debug_assertions)]
-    for song in &contents {
-        println!("{:?}", song);
+    if let Some(why) = page_out_of_range(page, count) {
+        return send_simple_message(ctx, msg, &why);
     }
 
     if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e| {
-            e.title(&format!("{}버튼 {}레벨 검색 결과:", 4, level));
-            e.description(&format!(
+            if bpm.min == bpm.max {
+                e.title(format!("BPM {} 검색 결과:", bpm.min));
+            } else {
+                e.title(format!("BPM {}~{} 검색 결과:", bpm.min, bpm.max));
+            }
+            e.description(format!(
                 "총 {}곡이 발견되었습니다. ({} / {} 페이지)",
-                contents.len(),
+                count,
                 page,
-                count / 25 + 1
+                page_count(count)
             ));
             let mut fields = Vec::new();
             for content in contents {
-                let mut pattern = String::new();
-                if level == content.four_button_0 {
-                    pattern.push_str("NM ");
-                }
-                if Some(level) == content.four_button_1 {
-                    pattern.push_str("HD ");
-                }
-                if Some(level) == content.four_button_2 {
-                    pattern.push_str("MX ");
-                }
-                if Some(level) == content.four_button_3 {
-                    pattern.push_str("SC ");
-                }
-                fields.push((content.title, pattern, true));
+                let bpm_string = format!("BPM {}\n{}", format_bpm(&content), content.category);
+                fields.push((content.title, bpm_string, true));
             }
             e.fields(fields);
             e
@@ -173,101 +1164,76 @@ pub fn search_by_4b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> C
 }
 
 #[command]
-#[aliases(
-    "5blv",
-    "5lv",
-    "5l",
-    "5",
-    "5버튼레벨",
-    "5키레벨",
-    "5버튼렙",
-    "5키렙",
-    "5버튼",
-    "5키"
-)]
+#[aliases("notes", "n", "노트수")]
 #[bucket = "djmax"]
-pub fn search_by_5b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.len() < 1 || args.len() > 2 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n사용법: `search_by_5b_level 레벨 (페이지)`",
-        );
-    }
-    let arg = args.single::<i32>();
-    if let Err(e) = arg {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.`",
-        );
-    }
-    let level = arg.unwrap();
-    if level > 15 || level < 1 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.",
-        );
-    }
-
+pub fn search_by_notes(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut modes = Vec::new();
+    let mut difficulties = Vec::new();
     let mut page: i64 = 1;
-    if !args.is_empty() {
-        let arg = args.single::<i64>();
-        if let Err(e) = arg {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.`",
-            );
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        if let Ok(difficulty) = arg.parse::<Difficulty>() {
+            difficulties.push(difficulty);
+            continue;
         }
-        page = arg.unwrap();
-        if page <= 0 {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.",
-            );
+        if let Ok(mode) = arg.parse::<ButtonMode>() {
+            if arg.chars().any(|c| !c.is_ascii_digit()) {
+                modes.push(mode);
+                continue;
+            }
         }
+        page = match arg.parse::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n사용법: `search_by_notes (버튼) (난이도) (페이지)`",
+                );
+            }
+        };
+    }
+    if modes.is_empty() {
+        modes = ButtonMode::ALL.to_vec();
+    }
+    if difficulties.is_empty() {
+        difficulties = Difficulty::ALL.to_vec();
     }
 
-    let conn = establish_connection();
-    let (count, contents) = Song::by_level(5, level, 25 * (page - 1), &conn);
+    let catalog = catalog(ctx)?;
+    let (count, contents) = catalog.by_note_count(&modes, &difficulties, page_offset(page));
     if count == 0 {
         return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
     }
-
-    // For debug purpose
-    #[cfg(debug_assertions)]
-    for song in &contents {
-        println!("{:?}", song);
+    if let Some(why) = page_out_of_range(page, count) {
+        return send_simple_message(ctx, msg, &why);
     }
 
     if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e| {
-            e.title(&format!("{}버튼 {}레벨 검색 결과:", 5, level));
-            e.description(&format!(
-                "총 {}곡이 발견되었습니다. ({} / {} 페이지)",
+            e.title("노트 수 순위:");
+            e.description(format!(
+                "총 {}개의 패턴이 발견되었습니다. ({} / {} 페이지)",
                 count,
                 page,
-                count / 25 + 1
+                page_count(count)
             ));
             let mut fields = Vec::new();
-            for content in contents {
-                let mut pattern = String::new();
-                if level == content.five_button_0 {
-                    pattern.push_str("NM ");
-                }
-                if Some(level) == content.five_button_1 {
-                    pattern.push_str("HD ");
+            for (i, (pattern, title)) in contents.into_iter().enumerate() {
+                let mut chart = String::new();
+                if let (Some(mode), Some(difficulty)) =
+                    (pattern.button_mode(), pattern.chart_difficulty())
+                {
+                    chart.push_str(&format!("{} {} {}\n", mode, difficulty, pattern.level));
                 }
-                if Some(level) == content.five_button_2 {
-                    pattern.push_str("MX ");
+                if let Some(note_count) = pattern.note_count {
+                    chart.push_str(&format!("{} notes", note_count));
                 }
-                if Some(level) == content.five_button_3 {
-                    pattern.push_str("SC ");
-                }
-                fields.push((content.title, pattern, true));
+                fields.push((
+                    format!("{}. {}", page_offset(page) + i as i64 + 1, title),
+                    chart,
+                    true,
+                ));
             }
             e.fields(fields);
             e
@@ -281,101 +1247,105 @@ pub fn search_by_5b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> C
 }
 
 #[command]
-#[aliases(
-    "6blv",
-    "6lv",
-    "6l",
-    "6",
-    "6버튼레벨",
-    "6키레벨",
-    "6버튼렙",
-    "6키렙",
-    "6버튼",
-    "6키"
-)]
+#[aliases("random", "r", "랜덤", "랜덤선곡")]
 #[bucket = "djmax"]
-pub fn search_by_6b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.len() < 1 || args.len() > 2 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n사용법: `search_by_6b_level 레벨 (페이지)`",
-        );
-    }
-    let arg = args.single::<i32>();
-    if let Err(e) = arg {
-        return send_simple_message(
-            ctx,
+pub fn random_chart(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let mut filter = ChartFilter::new(1, 15);
+    let mut modes = Vec::new();
+    let mut difficulties = Vec::new();
+    let mut draws = 1;
+    let mut first_positional = true;
+    let mut level_given = false;
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        match parse_filter_token(
+            arg.as_str(),
             msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.`",
-        );
-    }
-    let level = arg.unwrap();
-    if level > 15 || level < 1 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.",
-        );
-    }
-
-    let mut page: i64 = 1;
-    if !args.is_empty() {
-        let arg = args.single::<i64>();
-        if let Err(e) = arg {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.`",
-            );
+            &mut filter,
+            &mut difficulties,
+            &catalog,
+            &conn,
+        ) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(why) => return send_simple_message(ctx, msg, why.as_str()),
         }
-        page = arg.unwrap();
-        if page <= 0 {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.",
-            );
+        // Like `search_by_level`, a bare number is a button mode only when it
+        // comes first, so `random 6 12-14` means 6B charts of level 12 to 14.
+        let first = std::mem::replace(&mut first_positional, false);
+        if first || !arg.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(mode) = arg.parse::<ButtonMode>() {
+                modes.push(mode);
+                continue;
+            }
+        }
+        if let Some((min, max)) = parse_range::<i32>(arg.as_str()) {
+            if level_given {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n레벨은 한 번만 지정할 수 있습니다.",
+                );
+            }
+            if 1 <= min && min <= max && max <= 15 {
+                filter.min_level = min;
+                filter.max_level = max;
+                level_given = true;
+                continue;
+            }
+        }
+        match arg
+            .to_lowercase()
+            .strip_prefix('x')
+            .map(str::parse::<usize>)
+        {
+            Some(Ok(count)) if (1..=MAX_DRAWS).contains(&count) => draws = count,
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    &format!(
+                        "잘못된 사용법입니다.\n사용법: `random_chart (버튼) (레벨(-레벨)) (난이도) (pack:팩 이름) (owned) (x1~x{})`",
+                        MAX_DRAWS
+                    ),
+                );
+            }
         }
     }
-
-    let conn = establish_connection();
-    let (count, contents) = Song::by_level(6, level, 25 * (page - 1), &conn);
-    if count == 0 {
-        return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
+    if !modes.is_empty() {
+        filter.modes = modes;
+    }
+    if !difficulties.is_empty() {
+        filter.difficulties = difficulties;
     }
 
-    // For debug purpose
-    #[cfg(debug_assertions)]
-    for song in &contents {
-        println!("{:?}", song);
+    let mut rng = rand::thread_rng();
+    let mut contents = catalog.all_by_level(&filter);
+    contents.shuffle(&mut rng);
+    let picks: Vec<(Song, ButtonMode, Difficulty, i32)> = contents
+        .into_iter()
+        .take(draws)
+        .filter_map(|content| {
+            let chart = *filter.matching_charts(&content).choose(&mut rng)?;
+            Some((content, chart.0, chart.1, chart.2))
+        })
+        .collect();
+    if picks.is_empty() {
+        return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
     }
 
     if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e| {
-            e.title(&format!("{}버튼 {}레벨 검색 결과:", 6, level));
-            e.description(&format!(
-                "총 {}곡이 발견되었습니다. ({} / {} 페이지)",
-                count,
-                page,
-                count / 25 + 1
-            ));
+            e.title(format!("{} 랜덤 선곡 결과:", describe_filter(&filter)));
             let mut fields = Vec::new();
-            for content in contents {
-                let mut pattern = String::new();
-                if level == content.six_button_0 {
-                    pattern.push_str("NM ");
-                }
-                if Some(level) == content.six_button_1 {
-                    pattern.push_str("HD ");
-                }
-                if Some(level) == content.six_button_2 {
-                    pattern.push_str("MX ");
-                }
-                if Some(level) == content.six_button_3 {
-                    pattern.push_str("SC ");
-                }
-                fields.push((content.title, pattern, true));
+            for (i, (content, mode, difficulty, level)) in picks.into_iter().enumerate() {
+                fields.push((
+                    format!("{}. {}", i + 1, content.title),
+                    format!("{} {} {}\n{}", mode, difficulty, level, content.artist),
+                    false,
+                ));
             }
             e.fields(fields);
             e
@@ -388,6 +1358,82 @@ pub fn search_by_6b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> C
     Ok(())
 }
 
+#[command]
+#[aliases("lv", "level", "레벨", "렙")]
+#[bucket = "djmax"]
+pub fn search_by_level(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let arg = args.single::<String>().unwrap_or_default();
+    let modes = match arg.to_lowercase().as_str() {
+        "any" | "all" | "전체" => ButtonMode::ALL.to_vec(),
+        _ => match arg.parse::<ButtonMode>() {
+            Ok(mode) => vec![mode],
+            Err(_) => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n버튼은 4B, 5B, 6B, 8B, any 중 하나여야 합니다.",
+                );
+            }
+        },
+    };
+
+    search_level(ctx, msg, modes, args)
+}
+
+#[command]
+#[aliases(
+    "4blv",
+    "4lv",
+    "4l",
+    "4",
+    "4버튼레벨",
+    "4키레벨",
+    "4버튼렙",
+    "4키렙",
+    "4버튼",
+    "4키"
+)]
+#[bucket = "djmax"]
+pub fn search_by_4b_level(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    search_level(ctx, msg, vec![ButtonMode::Four], args)
+}
+
+#[command]
+#[aliases(
+    "5blv",
+    "5lv",
+    "5l",
+    "5",
+    "5버튼레벨",
+    "5키레벨",
+    "5버튼렙",
+    "5키렙",
+    "5버튼",
+    "5키"
+)]
+#[bucket = "djmax"]
+pub fn search_by_5b_level(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    search_level(ctx, msg, vec![ButtonMode::Five], args)
+}
+
+#[command]
+#[aliases(
+    "6blv",
+    "6lv",
+    "6l",
+    "6",
+    "6버튼레벨",
+    "6키레벨",
+    "6버튼렙",
+    "6키렙",
+    "6버튼",
+    "6키"
+)]
+#[bucket = "djmax"]
+pub fn search_by_6b_level(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    search_level(ctx, msg, vec![ButtonMode::Six], args)
+}
+
 #[command]
 #[aliases(
     "8blv",
@@ -402,96 +1448,33 @@ pub fn search_by_6b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> C
     "8키"
 )]
 #[bucket = "djmax"]
-pub fn search_by_8b_level(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.len() < 1 || args.len() > 2 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n사용법: `search_by_8b_level 레벨 (페이지)`",
-        );
-    }
-    let arg = args.single::<i32>();
-    if let Err(e) = arg {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.`",
-        );
-    }
-    let level = arg.unwrap();
-    if level > 15 || level < 1 {
-        return send_simple_message(
-            ctx,
-            msg,
-            "잘못된 사용법입니다.\n레벨은 1 이상 15 이하의 정수여야 합니다.",
-        );
-    }
+pub fn search_by_8b_level(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    search_level(ctx, msg, vec![ButtonMode::Eight], args)
+}
 
-    let mut page: i64 = 1;
-    if !args.is_empty() {
-        let arg = args.single::<i64>();
-        if let Err(e) = arg {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.`",
-            );
-        }
-        page = arg.unwrap();
-        if page <= 0 {
-            return send_simple_message(
-                ctx,
-                msg,
-                "잘못된 사용법입니다.\n페이지는 1 이상의 정수여야 합니다.",
-            );
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let conn = establish_connection();
-    let (count, contents) = Song::by_level(8, level, 25 * (page - 1), &conn);
-    if count == 0 {
-        return send_simple_message(ctx, msg, "검색 결과가 없습니다.");
+    #[test]
+    fn bracket_speeds_brackets_the_target() {
+        // `~디맥배속설정 520 ANALYS` -> `170 * 3.00 = 510, 170 * 3.25 = 552.5`
+        assert_eq!(bracket_speeds(170.0, 520.0), (3.0, 3.25));
+        assert_eq!(bracket_speeds(170.0, 510.0), (3.0, 3.25));
+        assert!(in_speed_range(170.0, 520.0));
     }
 
-    // For debug purpose
-    #[cfg(debug_assertions)]
-    for song in &contents {
-        println!("{:?}", song);
+    #[test]
+    fn bracket_speeds_clamps_to_the_speed_range() {
+        assert_eq!(bracket_speeds(300.0, 200.0), (1.0, 1.25));
+        assert_eq!(bracket_speeds(100.0, 900.0), (4.75, 5.0));
+        assert!(!in_speed_range(300.0, 200.0));
+        assert!(!in_speed_range(100.0, 900.0));
     }
 
-    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
-        m.embed(|e| {
-            e.title(&format!("{}버튼 {}레벨 검색 결과:", 8, level));
-            e.description(&format!(
-                "총 {}곡이 발견되었습니다. ({} / {} 페이지)",
-                count,
-                page,
-                count / 25 + 1
-            ));
-            let mut fields = Vec::new();
-            for content in contents {
-                let mut pattern = String::new();
-                if level == content.eight_button_0 {
-                    pattern.push_str("NM ");
-                }
-                if Some(level) == content.eight_button_1 {
-                    pattern.push_str("HD ");
-                }
-                if Some(level) == content.eight_button_2 {
-                    pattern.push_str("MX ");
-                }
-                if Some(level) == content.eight_button_3 {
-                    pattern.push_str("SC ");
-                }
-                fields.push((content.title, pattern, true));
-            }
-            e.fields(fields);
-            e
-        });
-        m
-    }) {
-        println!("Error sending message: {:?}", why);
+    #[test]
+    fn format_speeds_warns_outside_the_speed_range() {
+        assert!(!format_speeds(170.0, 520.0).contains("범위"));
+        assert!(format_speeds(300.0, 200.0).contains("범위"));
     }
-
-    Ok(())
 }
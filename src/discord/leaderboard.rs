@@ -0,0 +1,149 @@
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    prelude::*,
+};
+
+use super::djmax::{catalog, connection, send_simple_message};
+use super::records::{find_song, pop_chart};
+use crate::db::models::{ButtonMode, GuildScore};
+
+/// Number of players shown on a leaderboard.
+const LEADERBOARD_SIZE: i64 = 10;
+
+#[command]
+#[aliases("순위", "리더보드")]
+#[only_in(guilds)]
+#[bucket = "djmax"]
+pub fn top(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let mut tokens: Vec<&str> = args.rest().split_whitespace().collect();
+    let (mode, difficulty) = match pop_chart(&mut tokens) {
+        Some(chart) if !tokens.is_empty() => chart,
+        _ => {
+            return send_simple_message(
+                ctx,
+                msg,
+                "잘못된 사용법입니다.\n사용법: `top (곡 제목) (버튼) (난이도)`",
+            );
+        }
+    };
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let content = match find_song(tokens.join(" ").as_str(), &catalog) {
+        Ok(content) => content,
+        Err(reply) => return send_simple_message(ctx, msg, &reply),
+    };
+    let level = match content.level(mode, difficulty) {
+        Some(level) => level,
+        None => {
+            return send_simple_message(
+                ctx,
+                msg,
+                &format!(
+                    "{}에는 {} {} 패턴이 없습니다.",
+                    content.title, mode, difficulty
+                ),
+            );
+        }
+    };
+
+    let scores = GuildScore::top(
+        guild_id.0 as i64,
+        content.id,
+        mode,
+        difficulty,
+        LEADERBOARD_SIZE,
+        &conn,
+    )?;
+    if scores.is_empty() {
+        return send_simple_message(
+            ctx,
+            msg,
+            &format!(
+                "{} {} {}에 제출된 기록이 없습니다. `record add`로 기록을 제출해 주세요.",
+                content.title, mode, difficulty
+            ),
+        );
+    }
+
+    let mut list = String::new();
+    for (i, score) in scores.iter().enumerate() {
+        list.push_str(&format!(
+            "{}. <@{}> {:.2}%",
+            i + 1,
+            score.user_id,
+            score.rate
+        ));
+        if score.max_combo {
+            list.push_str(" (MAX COMBO)");
+        }
+        list.push('\n');
+    }
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!(
+                "{} {} {} {} 서버 순위:",
+                content.title, mode, difficulty, level
+            ));
+            e.description(list);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("랭킹")]
+#[only_in(guilds)]
+#[bucket = "djmax"]
+pub fn rank(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let mode = match args.single::<ButtonMode>() {
+        Ok(mode) if args.is_empty() => mode,
+        _ => {
+            return send_simple_message(ctx, msg, "잘못된 사용법입니다.\n사용법: `rank (버튼)`");
+        }
+    };
+
+    let conn = connection(ctx)?;
+    let standings = GuildScore::standings(guild_id.0 as i64, mode, &conn)?;
+    if standings.is_empty() {
+        return send_simple_message(ctx, msg, &format!("{}에 제출된 기록이 없습니다.", mode));
+    }
+
+    let mut list = String::new();
+    for (i, standing) in standings.iter().take(LEADERBOARD_SIZE as usize).enumerate() {
+        list.push_str(&format!(
+            "{}. <@{}> {:.2}점 ({}패턴)\n",
+            i + 1,
+            standing.user_id,
+            standing.total,
+            standing.charts
+        ));
+    }
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{} 서버 랭킹:", mode));
+            e.description(list);
+            e.footer(|f| f.text("제출한 모든 패턴의 정확도 합계 기준"));
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,601 @@
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+    prelude::*,
+};
+
+use chrono::Utc;
+use diesel::connection::Connection;
+use diesel::sqlite::SqliteConnection;
+
+use super::djmax::{
+    catalog, connection, page_count, page_offset, page_out_of_range, send_simple_message,
+    MAX_CANDIDATES,
+};
+use crate::catalog::Catalog;
+use crate::db::models::{
+    ButtonMode, ChartFilter, ClearRecord, Difficulty, GuildScore, PackFilter, Record, Song,
+};
+use crate::db::Error;
+use crate::rating::{self, ChartPoint, Rating};
+
+/// Number of rejected lines listed after an import.
+const MAX_SKIPPED_LINES: usize = 10;
+
+/// Formats a record as `6B SC 12 · 99.52% (MAX COMBO)`.
+fn format_record(record: &Record, song: Option<&Song>) -> String {
+    let mut line = String::new();
+    if let (Some(mode), Some(difficulty)) = (record.button_mode(), record.chart_difficulty()) {
+        line.push_str(&format!("{} {}", mode, difficulty));
+        if let Some(level) = song.and_then(|song| song.level(mode, difficulty)) {
+            line.push_str(&format!(" {}", level));
+        }
+        line.push_str(" · ");
+    }
+    line.push_str(&format!("{:.2}%", record.rate));
+    if record.max_combo {
+        line.push_str(" (MAX COMBO)");
+    }
+    line
+}
+
+/// Takes the trailing `<mode> <difficulty>` pair off `tokens`.
+pub(super) fn pop_chart(tokens: &mut Vec<&str>) -> Option<(ButtonMode, Difficulty)> {
+    if tokens.len() < 2 {
+        return None;
+    }
+    let difficulty = tokens.pop()?.parse::<Difficulty>().ok()?;
+    let mode = tokens.pop()?.parse::<ButtonMode>().ok()?;
+    Some((mode, difficulty))
+}
+
+/// Resolves `query` to a single song. A title or alias matching exactly wins;
+/// otherwise the search must find only one song. Returns the reply to send
+/// when nothing or more than one song matches.
+pub(super) fn find_song(query: &str, catalog: &Catalog) -> Result<Song, String> {
+    let mut contents = catalog.exact_title(query);
+    if contents.is_empty() {
+        contents = catalog.search_title(query, &PackFilter::default());
+    }
+    match contents.len() {
+        0 => Err("검색 결과가 없습니다.".to_string()),
+        1 => Ok(contents.remove(0)),
+        count => {
+            let mut reply = format!(
+                "\"{}\" 검색 결과가 여러 곡입니다. 곡 제목을 정확히 입력해 주세요.\n",
+                query
+            );
+            for content in contents.iter().take(MAX_CANDIDATES) {
+                reply.push_str(&format!("- {} - {}\n", content.title, content.artist));
+            }
+            if count > MAX_CANDIDATES {
+                reply.push_str(&format!("외 {}곡", count - MAX_CANDIDATES));
+            }
+            Err(reply)
+        }
+    }
+}
+
+/// Saves `record` and, when sent in a guild, submits it to the guild leaderboard,
+/// so that either both are stored or neither is. Returns the previous record
+/// when it was kept.
+fn save_record(
+    msg: &Message,
+    record: &Record,
+    conn: &SqliteConnection,
+) -> Result<Option<Record>, Error> {
+    conn.transaction::<_, Error, _>(|| {
+        if let Some(guild_id) = msg.guild_id {
+            GuildScore::submit(
+                &GuildScore {
+                    guild_id: guild_id.0 as i64,
+                    user_id: record.user_id,
+                    song_id: record.song_id,
+                    mode: record.mode,
+                    difficulty: record.difficulty,
+                    rate: record.rate,
+                    max_combo: record.max_combo,
+                    submitted_at: record.cleared_at,
+                },
+                conn,
+            )?;
+        }
+        Record::save(record, conn)
+    })
+}
+
+/// Handles `record import` with a CSV file of clears attached, whose header is
+/// `songId,mode,difficulty,rate,maxCombo`.
+fn import_records(ctx: &mut Context, msg: &Message) -> CommandResult {
+    let attachment = match msg.attachments.first() {
+        Some(attachment) => attachment,
+        None => {
+            return send_simple_message(
+                ctx,
+                msg,
+                "잘못된 사용법입니다.\n`songId,mode,difficulty,rate,maxCombo` 형식의 CSV 파일을 첨부해 주세요.",
+            );
+        }
+    };
+    let content = match attachment.download() {
+        Ok(content) => content,
+        Err(why) => {
+            println!("Error downloading attachment: {:?}", why);
+            return send_simple_message(ctx, msg, "첨부 파일을 내려받지 못했습니다.");
+        }
+    };
+
+    let conn = connection(ctx)?;
+    let user_id = msg.author.id.0 as i64;
+    let cleared_at = Utc::now().naive_utc();
+    let mut saved = 0;
+    let mut skipped = Vec::new();
+    let mut rdr = csv::Reader::from_reader(content.as_slice());
+    for (line, result) in rdr.deserialize::<ClearRecord>().enumerate() {
+        // The header is line 1.
+        let line = line + 2;
+        let record = result
+            .map_err(|why| Error::Invalid(why.to_string()))
+            .and_then(|clear| Record::from_clear(user_id, &clear, cleared_at, &conn));
+        match record {
+            Ok(record) => {
+                save_record(msg, &record, &conn)?;
+                saved += 1;
+            }
+            Err(Error::Invalid(why)) => skipped.push(format!("{}번째 줄: {}", line, why)),
+            Err(why) => return Err(why.into()),
+        }
+    }
+
+    let mut report = format!("{}개의 기록을 가져왔습니다.", saved);
+    if !skipped.is_empty() {
+        report.push_str(&format!("\n{}개의 줄을 건너뛰었습니다:", skipped.len()));
+        for line in skipped.iter().take(MAX_SKIPPED_LINES) {
+            report.push_str(&format!("\n{}", line));
+        }
+        if skipped.len() > MAX_SKIPPED_LINES {
+            report.push_str("\n...");
+        }
+    }
+    send_simple_message(ctx, msg, &report)
+}
+
+/// Handles `record add <title> <mode> <difficulty> <rate> [mc]`.
+fn add_record(ctx: &mut Context, msg: &Message, args: &str) -> CommandResult {
+    const USAGE: &str =
+        "잘못된 사용법입니다.\n사용법: `record add (곡 제목) (버튼) (난이도) (정확도) (mc)`";
+
+    let mut tokens: Vec<&str> = args.split_whitespace().collect();
+    let max_combo = match tokens.last() {
+        Some(token) if ["mc", "maxcombo", "맥콤"].contains(&token.to_lowercase().as_str()) => {
+            tokens.pop();
+            true
+        }
+        _ => false,
+    };
+    if tokens.len() < 4 {
+        return send_simple_message(ctx, msg, USAGE);
+    }
+    let rate = match tokens.pop().unwrap().trim_end_matches('%').parse::<f64>() {
+        Ok(rate) if (0.0..=100.0).contains(&rate) => rate,
+        _ => {
+            return send_simple_message(
+                ctx,
+                msg,
+                "잘못된 사용법입니다.\n정확도는 0 이상 100 이하의 수여야 합니다.",
+            );
+        }
+    };
+    let (mode, difficulty) = match pop_chart(&mut tokens) {
+        Some(chart) => chart,
+        None => return send_simple_message(ctx, msg, USAGE),
+    };
+
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let query = tokens.join(" ");
+    let content = match find_song(query.as_str(), &catalog) {
+        Ok(content) => content,
+        Err(reply) => return send_simple_message(ctx, msg, &reply),
+    };
+    if content.pattern(mode, difficulty).is_none() {
+        return send_simple_message(
+            ctx,
+            msg,
+            &format!(
+                "{}에는 {} {} 패턴이 없습니다.",
+                content.title, mode, difficulty
+            ),
+        );
+    }
+
+    let record = Record {
+        user_id: msg.author.id.0 as i64,
+        song_id: content.id,
+        mode: mode.buttons(),
+        difficulty: difficulty.index(),
+        rate,
+        max_combo,
+        cleared_at: Utc::now().naive_utc(),
+    };
+    let kept = save_record(msg, &record, &conn)?;
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(content.title.as_str());
+            match &kept {
+                Some(previous) => e.description(format!(
+                    "기존 기록이 더 좋아 유지되었습니다.\n{}",
+                    format_record(previous, Some(&content))
+                )),
+                None => e.description(format!(
+                    "기록이 저장되었습니다.\n{}",
+                    format_record(&record, Some(&content))
+                )),
+            };
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("기록")]
+#[bucket = "djmax"]
+pub fn record(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    if args.is_empty() {
+        return send_simple_message(
+            ctx,
+            msg,
+            "잘못된 사용법입니다.\n사용법: `record (곡 제목)`, `record add (곡 제목) (버튼) (난이도) (정확도) (mc)` 또는 CSV 파일을 첨부한 `record import`",
+        );
+    }
+    let first = args.single::<String>()?;
+    if first.eq_ignore_ascii_case("add") || first == "추가" {
+        return add_record(ctx, msg, args.rest());
+    }
+    if first.eq_ignore_ascii_case("import") || first == "가져오기" {
+        return import_records(ctx, msg);
+    }
+
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let query = format!("{} {}", first, args.rest());
+    let content = match find_song(query.trim(), &catalog) {
+        Ok(content) => content,
+        Err(reply) => return send_simple_message(ctx, msg, &reply),
+    };
+
+    let records = Record::of_song(msg.author.id.0 as i64, content.id, &conn)?;
+    if records.is_empty() {
+        return send_simple_message(
+            ctx,
+            msg,
+            &format!("{}에 저장된 기록이 없습니다.", content.title),
+        );
+    }
+
+    let mut list = String::new();
+    for record in &records {
+        list.push_str(&format!(
+            "{} ({})\n",
+            format_record(record, Some(&content)),
+            record.cleared_at.format("%Y-%m-%d")
+        ));
+    }
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{}님의 {} 기록:", msg.author.name, content.title));
+            e.description(list);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("기록목록")]
+#[bucket = "djmax"]
+pub fn records(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut modes = Vec::new();
+    let mut page: i64 = 1;
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        if let Ok(mode) = arg.parse::<ButtonMode>() {
+            if arg.chars().any(|c| !c.is_ascii_digit()) {
+                modes.push(mode);
+                continue;
+            }
+        }
+        page = match arg.parse::<i64>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n사용법: `records (버튼) (페이지)`",
+                );
+            }
+        };
+    }
+    if modes.is_empty() {
+        modes = ButtonMode::ALL.to_vec();
+    }
+
+    let conn = connection(ctx)?;
+    let (count, contents) =
+        Record::of_user(msg.author.id.0 as i64, &modes, page_offset(page), &conn)?;
+    if count == 0 {
+        return send_simple_message(ctx, msg, "저장된 기록이 없습니다.");
+    }
+    if let Some(why) = page_out_of_range(page, count) {
+        return send_simple_message(ctx, msg, &why);
+    }
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{}님의 기록:", msg.author.name));
+            e.description(format!(
+                "총 {}개의 기록이 있습니다. ({} / {} 페이지)",
+                count,
+                page,
+                page_count(count)
+            ));
+            let mut fields = Vec::new();
+            for (i, (record, title)) in contents.into_iter().enumerate() {
+                fields.push((
+                    format!("{}. {}", page_offset(page) + i as i64 + 1, title),
+                    format_record(&record, None),
+                    true,
+                ));
+            }
+            e.fields(fields);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+/// Number of charts listed in a rating breakdown.
+const BREAKDOWN_SIZE: usize = 15;
+
+fn rating_of(
+    user: i64,
+    mode: ButtonMode,
+    catalog: &Catalog,
+    conn: &SqliteConnection,
+) -> Result<Rating, Error> {
+    let records = Record::of_user_in_mode(user, mode, conn)?;
+    let ids: Vec<i32> = records.iter().map(|record| record.song_id).collect();
+    let songs = catalog.by_ids(&ids, &PackFilter::default());
+    Ok(rating::rate(&records, &songs))
+}
+
+fn format_chart_point(chart: &ChartPoint) -> String {
+    let mut line = format!(
+        "{} {} {} · {:.2}%",
+        chart.title, chart.difficulty, chart.level, chart.rate
+    );
+    if chart.max_combo {
+        line.push_str(" (MC)");
+    }
+    line.push_str(&format!(" → {:.2}", chart.point));
+    line
+}
+
+#[command]
+#[aliases("djpower", "파워", "레이팅")]
+#[bucket = "djmax"]
+pub fn power(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = msg.mentions.first().unwrap_or(&msg.author);
+    let mut mode = None;
+    while !args.is_empty() {
+        let arg = args.single::<String>()?;
+        if arg.starts_with("<@") {
+            continue;
+        }
+        mode = match arg.parse::<ButtonMode>() {
+            Ok(mode) => Some(mode),
+            Err(_) => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n사용법: `power (@사용자) (버튼)`",
+                );
+            }
+        };
+    }
+
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let user_id = user.id.0 as i64;
+    let result = match mode {
+        Some(mode) => {
+            let rating = rating_of(user_id, mode, &catalog, &conn)?;
+            if rating.charts.is_empty() {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    &format!("{}님의 {} 기록이 없습니다.", user.name, mode),
+                );
+            }
+            let mut breakdown = String::new();
+            for (i, chart) in rating.charts.iter().take(BREAKDOWN_SIZE).enumerate() {
+                breakdown.push_str(&format!("{}. {}\n", i + 1, format_chart_point(chart)));
+            }
+            if rating.charts.len() > BREAKDOWN_SIZE {
+                breakdown.push_str(&format!(
+                    "... 외 {}개 패턴\n",
+                    rating.charts.len() - BREAKDOWN_SIZE
+                ));
+            }
+            msg.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.title(format!(
+                        "{}님의 {} POWER: {:.2}",
+                        user.name, mode, rating.power
+                    ));
+                    e.description(breakdown);
+                    e.footer(|f| f.text(format!("상위 {}개 패턴 기준", rating::TOP_CHARTS)));
+                    e
+                });
+                m
+            })
+        }
+        None => {
+            let mut ratings: Vec<(ButtonMode, Rating)> = Vec::new();
+            for &mode in ButtonMode::ALL.iter() {
+                let rating = rating_of(user_id, mode, &catalog, &conn)?;
+                if !rating.charts.is_empty() {
+                    ratings.push((mode, rating));
+                }
+            }
+            if ratings.is_empty() {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    &format!("{}님의 기록이 없습니다.", user.name),
+                );
+            }
+            msg.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.title(format!("{}님의 POWER:", user.name));
+                    let mut fields = Vec::new();
+                    for (mode, rating) in &ratings {
+                        fields.push((
+                            format!("{}: {:.2}", mode, rating.power),
+                            format!(
+                                "{}개 패턴\n최고: {}",
+                                rating.charts.len(),
+                                format_chart_point(&rating.charts[0])
+                            ),
+                            false,
+                        ));
+                    }
+                    e.fields(fields);
+                    e.footer(|f| {
+                        f.text(format!(
+                            "버튼별 상위 {}개 패턴 기준, `power (버튼)`으로 자세히 보기",
+                            rating::TOP_CHARTS
+                        ))
+                    });
+                    e
+                });
+                m
+            })
+        }
+    };
+    if let Err(why) = result {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("rec", "추천")]
+#[bucket = "djmax"]
+pub fn recommend(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = if args.is_empty() {
+        None
+    } else {
+        match args.single::<ButtonMode>() {
+            Ok(mode) if args.is_empty() => Some(mode),
+            _ => {
+                return send_simple_message(
+                    ctx,
+                    msg,
+                    "잘못된 사용법입니다.\n사용법: `recommend (버튼)`",
+                );
+            }
+        }
+    };
+
+    let catalog = catalog(ctx)?;
+    let conn = connection(ctx)?;
+    let user_id = msg.author.id.0 as i64;
+    // Without a mode, recommend for the mode with the most records.
+    let (mode, records) = match mode {
+        Some(mode) => (mode, Record::of_user_in_mode(user_id, mode, &conn)?),
+        None => {
+            let mut most = (ButtonMode::ALL[0], Vec::new());
+            for &mode in ButtonMode::ALL.iter() {
+                let records = Record::of_user_in_mode(user_id, mode, &conn)?;
+                if records.len() > most.1.len() {
+                    most = (mode, records);
+                }
+            }
+            most
+        }
+    };
+    let ids: Vec<i32> = records.iter().map(|record| record.song_id).collect();
+    let songs = catalog.by_ids(&ids, &PackFilter::default());
+    let comfort = match rating::rate(&records, &songs).comfort() {
+        Some(comfort) => comfort,
+        None => {
+            return send_simple_message(
+                ctx,
+                msg,
+                &format!(
+                    "{} 기록이 없어 추천할 수 없습니다. `record add`나 `record import`로 기록을 남겨 주세요.",
+                    mode
+                ),
+            );
+        }
+    };
+
+    let center = comfort.level.round() as i32;
+    let mut filter = ChartFilter::new(center - 1, center + 1);
+    filter.modes = vec![mode];
+    let candidates = catalog.all_by_level(&filter);
+    let suggestions = rating::recommend(
+        mode,
+        comfort,
+        &records,
+        &candidates,
+        &mut rand::thread_rng(),
+    );
+    if suggestions.is_empty() {
+        return send_simple_message(ctx, msg, "추천할 패턴이 없습니다.");
+    }
+
+    if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(format!("{}님을 위한 {} 추천 패턴:", msg.author.name, mode));
+            e.description(format!(
+                "평소 레벨 {:.1}, 평소 정확도 {:.2}% 기준",
+                comfort.level, comfort.rate
+            ));
+            let mut fields = Vec::new();
+            for suggestion in suggestions {
+                fields.push((
+                    format!(
+                        "{} {} {}",
+                        suggestion.title, suggestion.difficulty, suggestion.level
+                    ),
+                    suggestion.reason.to_string(),
+                    false,
+                ));
+            }
+            e.fields(fields);
+            e
+        });
+        m
+    }) {
+        println!("Error sending message: {:?}", why);
+    }
+
+    Ok(())
+}
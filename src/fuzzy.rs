@@ -0,0 +1,134 @@
+use crate::db::models::Song;
+use crate::hangul::to_jamo;
+
+/// Maximum number of suggestions returned for a single query.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// In-memory index over every song title for typo-tolerant lookups.
+pub struct TitleIndex {
+    titles: Vec<(i32, Vec<char>)>,
+}
+
+/// Lowercases `text`, strips everything but letters and digits and splits
+/// Hangul syllables into jamo.
+fn normalize(text: &str) -> Vec<char> {
+    let stripped: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    to_jamo(&stripped).chars().collect()
+}
+
+/// Returns the smallest edit distance between `query` and any substring of `text`.
+fn substring_distance(query: &[char], text: &[char]) -> usize {
+    // Starting anywhere in `text` is free, hence the zeroed first row.
+    let mut previous = vec![0; text.len() + 1];
+    let mut current = vec![0; text.len() + 1];
+    for (i, q) in query.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, t) in text.iter().enumerate() {
+            let substitution = previous[j] + if q == t { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous.into_iter().min().unwrap_or(0)
+}
+
+impl TitleIndex {
+    pub fn new(songs: &[Song]) -> Self {
+        TitleIndex {
+            titles: songs
+                .iter()
+                .map(|song| (song.id, normalize(&song.title)))
+                .collect(),
+        }
+    }
+
+    /// Returns the ids of the titles closest to `query`, best match first.
+    pub fn suggest(&self, query: &str) -> Vec<i32> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return vec![];
+        }
+
+        // Allow roughly one typo for every three jamo or letters typed.
+        let threshold = query.len() / 3;
+        let mut matches: Vec<(usize, usize, i32)> = self
+            .titles
+            .iter()
+            .filter_map(|(id, title)| {
+                let distance = substring_distance(&query, title);
+                if distance <= threshold {
+                    let extra = title.len().saturating_sub(query.len());
+                    Some((distance, extra, *id))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        matches.sort();
+        matches
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, _, id)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: i32, title: &str) -> Song {
+        Song {
+            id,
+            title: title.to_string(),
+            artist: String::new(),
+            min_bpm: None,
+            max_bpm: 120.0,
+            category: String::from("RP"),
+            dlc: None,
+            patterns: vec![],
+        }
+    }
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn substring_distance_ignores_the_rest_of_the_text() {
+        assert_eq!(substring_distance(&chars("wave"), &chars("airwave")), 0);
+        assert_eq!(substring_distance(&chars("wove"), &chars("airwave")), 1);
+        assert_eq!(substring_distance(&chars("waves"), &chars("airwave")), 1);
+        assert_eq!(substring_distance(&[], &chars("airwave")), 0);
+        assert_eq!(substring_distance(&chars("abc"), &[]), 3);
+    }
+
+    #[test]
+    fn suggests_titles_despite_typos() {
+        let index = TitleIndex::new(&[song(1, "비상 ~Stay With Me~"), song(2, "Airwave")]);
+        assert_eq!(index.suggest("비싱"), vec![1]);
+        assert_eq!(index.suggest("Airwav"), vec![2]);
+        assert_eq!(index.suggest("Ariwave"), vec![2]);
+    }
+
+    #[test]
+    fn prefers_closer_and_shorter_titles() {
+        let index = TitleIndex::new(&[
+            song(1, "Airwave Extended"),
+            song(2, "Airwave"),
+            song(3, "Airwove"),
+        ]);
+        assert_eq!(index.suggest("airwave"), vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn ignores_empty_queries() {
+        let index = TitleIndex::new(&[song(1, "Airwave")]);
+        assert!(index.suggest("").is_empty());
+        assert!(index.suggest("~!").is_empty());
+    }
+}
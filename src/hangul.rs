@@ -0,0 +1,140 @@
+// Hangul syllables are laid out as
+// 0xAC00 + (choseong * 21 + jungseong) * 28 + jongseong.
+const SYLLABLE_BASE: u32 = 0xAC00;
+const SYLLABLE_LAST: u32 = 0xD7A3;
+const JUNGSEONG_COUNT: u32 = 21;
+const JONGSEONG_COUNT: u32 = 28;
+
+// Compatibility jamo, which is what users type on a keyboard.
+const CHOSEONG: [char; 19] = [
+    'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ',
+    'ㅌ', 'ㅍ', 'ㅎ',
+];
+const JUNGSEONG: [char; 21] = [
+    'ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅘ', 'ㅙ', 'ㅚ', 'ㅛ', 'ㅜ', 'ㅝ', 'ㅞ',
+    'ㅟ', 'ㅠ', 'ㅡ', 'ㅢ', 'ㅣ',
+];
+const JONGSEONG: [Option<char>; 28] = [
+    None,
+    Some('ㄱ'),
+    Some('ㄲ'),
+    Some('ㄳ'),
+    Some('ㄴ'),
+    Some('ㄵ'),
+    Some('ㄶ'),
+    Some('ㄷ'),
+    Some('ㄹ'),
+    Some('ㄺ'),
+    Some('ㄻ'),
+    Some('ㄼ'),
+    Some('ㄽ'),
+    Some('ㄾ'),
+    Some('ㄿ'),
+    Some('ㅀ'),
+    Some('ㅁ'),
+    Some('ㅂ'),
+    Some('ㅄ'),
+    Some('ㅅ'),
+    Some('ㅆ'),
+    Some('ㅇ'),
+    Some('ㅈ'),
+    Some('ㅊ'),
+    Some('ㅋ'),
+    Some('ㅌ'),
+    Some('ㅍ'),
+    Some('ㅎ'),
+];
+
+/// Splits a Hangul syllable into its initial, medial and optional final jamo.
+pub fn decompose(syllable: char) -> Option<(char, char, Option<char>)> {
+    let code = syllable as u32;
+    if !(SYLLABLE_BASE..=SYLLABLE_LAST).contains(&code) {
+        return None;
+    }
+
+    let index = code - SYLLABLE_BASE;
+    let choseong = index / (JUNGSEONG_COUNT * JONGSEONG_COUNT);
+    let jungseong = index % (JUNGSEONG_COUNT * JONGSEONG_COUNT) / JONGSEONG_COUNT;
+    let jongseong = index % JONGSEONG_COUNT;
+    Some((
+        CHOSEONG[choseong as usize],
+        JUNGSEONG[jungseong as usize],
+        JONGSEONG[jongseong as usize],
+    ))
+}
+
+/// Replaces every Hangul syllable in `text` by its jamo, so that a partially
+/// typed syllable ("비사") is a prefix of the full one ("비상").
+pub fn to_jamo(text: &str) -> String {
+    let mut jamo = String::with_capacity(text.len() * 3);
+    for c in text.chars() {
+        match decompose(c) {
+            Some((choseong, jungseong, jongseong)) => {
+                jamo.push(choseong);
+                jamo.push(jungseong);
+                if let Some(jongseong) = jongseong {
+                    jamo.push(jongseong);
+                }
+            }
+            None => jamo.push(c),
+        }
+    }
+    jamo
+}
+
+/// Returns whether `text` consists only of initial consonants such as "ㅂㅅ".
+pub fn is_choseong_query(text: &str) -> bool {
+    let mut consonants = text.chars().filter(|c| !c.is_whitespace()).peekable();
+    consonants.peek().is_some() && consonants.all(|c| CHOSEONG.contains(&c))
+}
+
+/// Replaces every Hangul syllable in `text` by its initial consonant and drops
+/// whitespace and punctuation, so that "고백, 꽃, 늑대" becomes "ㄱㅂㄲㄴㄷ".
+pub fn to_choseong(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .map(|c| match decompose(c) {
+            Some((choseong, _, _)) => choseong,
+            None => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposes_syllables() {
+        assert_eq!(decompose('가'), Some(('ㄱ', 'ㅏ', None)));
+        assert_eq!(decompose('상'), Some(('ㅅ', 'ㅏ', Some('ㅇ'))));
+        assert_eq!(decompose('힣'), Some(('ㅎ', 'ㅣ', Some('ㅎ'))));
+    }
+
+    #[test]
+    fn leaves_other_characters_alone() {
+        assert_eq!(decompose('a'), None);
+        assert_eq!(decompose('ㄱ'), None);
+        assert_eq!(to_jamo("a1 비"), "a1 ㅂㅣ");
+    }
+
+    #[test]
+    fn partial_syllable_is_a_prefix() {
+        assert!(to_jamo("비상").starts_with(&to_jamo("비사")));
+    }
+
+    #[test]
+    fn reduces_titles_to_initial_consonants() {
+        assert_eq!(to_choseong("고백, 꽃, 늑대"), "ㄱㅂㄲㄴㄷ");
+        assert_eq!(to_choseong("비상 2"), "ㅂㅅ2");
+    }
+
+    #[test]
+    fn detects_initial_consonant_queries() {
+        assert!(is_choseong_query("ㅂㅅ"));
+        assert!(is_choseong_query("ㄱㅂ ㄲ"));
+        assert!(!is_choseong_query("ㅂ상"));
+        assert!(!is_choseong_query("ㅏㅣ"));
+        assert!(!is_choseong_query(" "));
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use csv::StringRecord;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+use crate::db;
+use crate::db::models::{ButtonMode, Difficulty, Song, SongAlias, SongRecord};
+
+/// Aliases shipped with the bot, imported when `ALIASES_CSV_FILE_NAME` is not set.
+const DEFAULT_ALIASES_CSV_FILE_NAME: &str = "djmax_song_aliases.csv";
+
+/// Returns the optional CSV file named by `var`, falling back to `default`
+/// when the variable is unset and that file exists.
+fn optional_csv(var: &str, default: &str) -> Option<String> {
+    match env::var(var) {
+        Ok(path) => Some(path),
+        Err(_) if Path::new(default).exists() => Some(String::from(default)),
+        Err(_) => None,
+    }
+}
+
+/// A field of a song whose value in the CSV differs from the database.
+#[derive(Debug)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A song missing from the CSV that is kept, because players logged records or
+/// guild scores on it that removing the song would lose.
+#[derive(Debug)]
+pub struct KeptSong {
+    pub song: Song,
+    pub records: i64,
+    pub scores: i64,
+}
+
+/// Difference between the songs CSV and the `songs` table.
+#[derive(Debug, Default)]
+pub struct SongDiff {
+    pub added: Vec<Song>,
+    pub changed: Vec<(Song, Vec<FieldChange>)>,
+    pub removed: Vec<Song>,
+    pub kept: Vec<KeptSong>,
+    /// Aliases whose song is neither in the CSV nor kept, which are not imported.
+    pub skipped_aliases: Vec<SongAlias>,
+}
+
+impl SongDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+            && self.kept.is_empty()
+            && self.skipped_aliases.is_empty()
+    }
+}
+
+impl fmt::Display for SongDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Songs are up to date");
+        }
+
+        writeln!(
+            f,
+            "Songs: {} added, {} changed, {} removed, {} kept",
+            self.added.len(),
+            self.changed.len(),
+            self.removed.len(),
+            self.kept.len()
+        )?;
+        for song in &self.added {
+            writeln!(f, "+ {} {}", song.id, song.title)?;
+        }
+        for (song, changes) in &self.changed {
+            let changes = changes
+                .iter()
+                .map(|change| format!("{}: {} -> {}", change.field, change.old, change.new))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "~ {} {} ({})", song.id, song.title, changes)?;
+        }
+        for song in &self.removed {
+            writeln!(f, "- {} {}", song.id, song.title)?;
+        }
+        for kept in &self.kept {
+            writeln!(
+                f,
+                "! {} {} is missing from the CSV but kept for its {} records and {} guild scores",
+                kept.song.id, kept.song.title, kept.records, kept.scores
+            )?;
+        }
+        for alias in &self.skipped_aliases {
+            writeln!(
+                f,
+                "! Alias {} of missing song {} is skipped",
+                alias.alias, alias.song_id
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| String::from("-"), ToString::to_string)
+}
+
+/// Lists the fields and chart levels that differ between two versions of a song.
+fn field_changes(old: &Song, new: &Song) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let mut compare = |field: String, old: String, new: String| {
+        if old != new {
+            changes.push(FieldChange { field, old, new });
+        }
+    };
+
+    compare("title".into(), old.title.clone(), new.title.clone());
+    compare("artist".into(), old.artist.clone(), new.artist.clone());
+    compare(
+        "min_bpm".into(),
+        format_optional(&old.min_bpm),
+        format_optional(&new.min_bpm),
+    );
+    compare(
+        "max_bpm".into(),
+        old.max_bpm.to_string(),
+        new.max_bpm.to_string(),
+    );
+    compare(
+        "category".into(),
+        old.category.clone(),
+        new.category.clone(),
+    );
+    compare(
+        "dlc".into(),
+        format_optional(&old.dlc),
+        format_optional(&new.dlc),
+    );
+    for &mode in ButtonMode::ALL.iter() {
+        for &difficulty in Difficulty::ALL.iter() {
+            compare(
+                format!("{} {}", mode, difficulty),
+                format_optional(&old.level(mode, difficulty)),
+                format_optional(&new.level(mode, difficulty)),
+            );
+
+            let old = old.pattern(mode, difficulty);
+            let new = new.pattern(mode, difficulty);
+            compare(
+                format!("{} {} notes", mode, difficulty),
+                format_optional(&old.and_then(|pattern| pattern.note_count)),
+                format_optional(&new.and_then(|pattern| pattern.note_count)),
+            );
+            compare(
+                format!("{} {} long notes", mode, difficulty),
+                format_optional(&old.and_then(|pattern| pattern.long_note_count)),
+                format_optional(&new.and_then(|pattern| pattern.long_note_count)),
+            );
+            compare(
+                format!("{} {} designer", mode, difficulty),
+                format_optional(&old.and_then(|pattern| pattern.designer.as_ref())),
+                format_optional(&new.and_then(|pattern| pattern.designer.as_ref())),
+            );
+        }
+    }
+    changes
+}
+
+/// Fills in the charts of `song` from the optional `4b0Notes`, `4b0LongNotes`
+/// and `4b0Designer` style columns of `row`. Missing or empty columns leave the
+/// details unknown.
+fn read_chart_details(
+    song: &mut Song,
+    headers: &StringRecord,
+    row: &StringRecord,
+) -> Result<(), Box<dyn Error>> {
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .and_then(|index| row.get(index))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let count = |name: &str| -> Result<Option<i32>, String> {
+        column(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Song {} has an invalid {}: {}", song.id, name, value))
+            })
+            .transpose()
+    };
+
+    let mut charts = Vec::with_capacity(song.patterns.len());
+    for pattern in &song.patterns {
+        let prefix = format!("{}b{}", pattern.mode, pattern.difficulty);
+        charts.push((
+            count(&format!("{}Notes", prefix))?,
+            count(&format!("{}LongNotes", prefix))?,
+            column(&format!("{}Designer", prefix)).map(String::from),
+        ));
+    }
+    for (pattern, (note_count, long_note_count, designer)) in song.patterns.iter_mut().zip(charts) {
+        pattern.note_count = note_count;
+        pattern.long_note_count = long_note_count;
+        pattern.designer = designer;
+    }
+    Ok(())
+}
+
+/// Reads the songs CSV at `file_path`.
+fn read_songs(file_path: &str) -> Result<BTreeMap<i32, Song>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let mut rdr = csv::Reader::from_reader(file);
+    let headers = rdr.headers()?.clone();
+
+    let mut songs = BTreeMap::new();
+    for result in rdr.records() {
+        let row = result?;
+        let record: SongRecord = row.deserialize(Some(&headers))?;
+        let mut song: Song = record.into();
+        read_chart_details(&mut song, &headers, &row)?;
+        if songs.contains_key(&song.id) {
+            return Err(format!("Song {} appears twice in {}", song.id, file_path).into());
+        }
+        songs.insert(song.id, song);
+    }
+    Ok(songs)
+}
+
+/// Reads the aliases CSV at `file_path`.
+fn read_aliases(file_path: &str) -> Result<Vec<SongAlias>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let mut rdr = csv::Reader::from_reader(file);
+    let mut aliases = Vec::new();
+    for result in rdr.deserialize() {
+        aliases.push(result?);
+    }
+    Ok(aliases)
+}
+
+/// Splits `aliases` into those whose song is left after applying `diff`, given
+/// the `ids` of the songs in the CSV, and those whose song is not.
+fn split_aliases(
+    aliases: Vec<SongAlias>,
+    ids: &BTreeSet<i32>,
+    diff: &SongDiff,
+) -> (Vec<SongAlias>, Vec<SongAlias>) {
+    aliases.into_iter().partition(|alias| {
+        ids.contains(&alias.song_id) || diff.kept.iter().any(|kept| kept.song.id == alias.song_id)
+    })
+}
+
+/// Compares the CSV files named in the environment with the database, and
+/// returns the difference along with the aliases to import.
+fn plan_csv(conn: &SqliteConnection) -> Result<(SongDiff, Vec<SongAlias>), Box<dyn Error>> {
+    let file_path = env::var("CSV_FILE_NAME").map_err(|_| "CSV_FILE_NAME must be set")?;
+    let songs = read_songs(&file_path)?;
+    let ids: BTreeSet<i32> = songs.keys().copied().collect();
+    let mut diff = diff_songs(songs, conn)?;
+
+    // aliases are optional, so only import them when a file is found
+    let mut aliases = Vec::new();
+    if let Some(alias_file_path) =
+        optional_csv("ALIASES_CSV_FILE_NAME", DEFAULT_ALIASES_CSV_FILE_NAME)
+    {
+        let (found, skipped) = split_aliases(read_aliases(&alias_file_path)?, &ids, &diff);
+        aliases = found;
+        diff.skipped_aliases = skipped;
+    }
+    Ok((diff, aliases))
+}
+
+/// Compares the CSV files named in the environment with the database without
+/// changing anything.
+pub fn diff_csv(conn: &SqliteConnection) -> Result<SongDiff, Box<dyn Error>> {
+    Ok(plan_csv(conn)?.0)
+}
+
+/// Compares `songs` with the `songs` table.
+fn diff_songs(
+    mut songs: BTreeMap<i32, Song>,
+    conn: &SqliteConnection,
+) -> Result<SongDiff, db::Error> {
+    let mut diff = SongDiff::default();
+    for current in Song::all(conn)? {
+        match songs.remove(&current.id) {
+            Some(song) => {
+                let changes = field_changes(&current, &song);
+                if !changes.is_empty() {
+                    diff.changed.push((song, changes));
+                }
+            }
+            None => match Song::player_data(current.id, conn)? {
+                (0, 0) => diff.removed.push(current),
+                (records, scores) => diff.kept.push(KeptSong {
+                    song: current,
+                    records,
+                    scores,
+                }),
+            },
+        }
+    }
+    diff.added = songs.into_values().collect();
+    Ok(diff)
+}
+
+/// Writes the songs of `diff` to the database. Kept songs are left alone.
+fn apply_diff(diff: &SongDiff, conn: &SqliteConnection) -> Result<(), db::Error> {
+    for song in &diff.added {
+        Song::insert(song, conn)?;
+    }
+    for (song, _) in &diff.changed {
+        Song::update(song, conn)?;
+    }
+    for song in &diff.removed {
+        Song::delete(song.id, conn)?;
+    }
+    Ok(())
+}
+
+/// Brings the database in line with the CSV files named in the environment in a
+/// single transaction, and returns what changed in the songs.
+pub fn import_csv(conn: &SqliteConnection) -> Result<SongDiff, Box<dyn Error>> {
+    conn.transaction::<_, Box<dyn Error>, _>(|| {
+        let (diff, aliases) = plan_csv(conn)?;
+        apply_diff(&diff, conn)?;
+        for alias in &aliases {
+            SongAlias::create(alias, conn)?;
+        }
+        Ok(diff)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::process;
+
+    use chrono::Utc;
+
+    use crate::db::models::Record;
+
+    const HEADER: &str = "id,title,artist,minBpm,maxBpm,category,dlc,\
+                          4b0,4b1,4b2,4b3,5b0,5b1,5b2,5b3,6b0,6b1,6b2,6b3,8b0,8b1,8b2,8b3";
+    const STAY_WITH_ME: &str =
+        "1,비상 ~Stay With Me~,Forte Escape,,142,RESPECT,,4,7,,,6,10,,,6,10,11,,7,11,,";
+    const TWO_NITE: &str = "2,2Nite,ND Lee,,110,RESPECT,,3,6,,,4,9,,,3,8,,,4,8,,";
+    const ARMORED_PHANTOM: &str =
+        "3,Armored Phantom,ND Lee,,180,RESPECT,,6,9,,,6,10,,,7,11,,,7,12,,";
+
+    /// Writes a songs CSV with `header` and `rows` to a temporary file and reads it back.
+    fn read_with_header(
+        name: &str,
+        header: &str,
+        rows: &[&str],
+    ) -> Result<BTreeMap<i32, Song>, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!("djmax_songs_{}_{}.csv", process::id(), name));
+        fs::write(&path, format!("{}\n{}\n", header, rows.join("\n")))?;
+        let songs = read_songs(path.to_str().unwrap());
+        fs::remove_file(&path)?;
+        songs
+    }
+
+    fn read(name: &str, rows: &[&str]) -> Result<BTreeMap<i32, Song>, Box<dyn Error>> {
+        read_with_header(name, HEADER, rows)
+    }
+
+    #[test]
+    fn diffs_and_imports_songs() {
+        let conn = db::establish_connection().unwrap();
+
+        let songs = read("initial", &[STAY_WITH_ME, TWO_NITE, ARMORED_PHANTOM]).unwrap();
+        let diff = diff_songs(songs, &conn).unwrap();
+        assert_eq!(diff.added.len(), 3);
+        assert!(diff.changed.is_empty() && diff.removed.is_empty() && diff.kept.is_empty());
+        apply_diff(&diff, &conn).unwrap();
+
+        let songs = read("unchanged", &[STAY_WITH_ME, TWO_NITE, ARMORED_PHANTOM]).unwrap();
+        assert!(diff_songs(songs, &conn).unwrap().is_empty());
+
+        let changed = STAY_WITH_ME.replacen(",4,7,", ",5,7,", 1);
+        let songs = read("changed", &[&changed, TWO_NITE, ARMORED_PHANTOM]).unwrap();
+        let diff = diff_songs(songs, &conn).unwrap();
+        assert_eq!(diff.changed.len(), 1);
+        let changes = &diff.changed[0].1;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].old.as_str(), changes[0].new.as_str()),
+            ("4", "5")
+        );
+    }
+
+    #[test]
+    fn keeps_missing_songs_with_player_data() {
+        let conn = db::establish_connection().unwrap();
+
+        let songs = read("before", &[STAY_WITH_ME, TWO_NITE, ARMORED_PHANTOM]).unwrap();
+        apply_diff(&diff_songs(songs, &conn).unwrap(), &conn).unwrap();
+        Record::save(
+            &Record {
+                user_id: 42,
+                song_id: 2,
+                mode: 4,
+                difficulty: 0,
+                rate: 98.5,
+                max_combo: false,
+                cleared_at: Utc::now().naive_utc(),
+            },
+            &conn,
+        )
+        .unwrap();
+
+        let songs = read("after", &[STAY_WITH_ME]).unwrap();
+        let diff = diff_songs(songs, &conn).unwrap();
+        assert_eq!(
+            diff.removed.iter().map(|song| song.id).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(diff.kept.len(), 1);
+        assert_eq!(
+            (
+                diff.kept[0].song.id,
+                diff.kept[0].records,
+                diff.kept[0].scores
+            ),
+            (2, 1, 0)
+        );
+        assert!(diff
+            .to_string()
+            .contains("kept for its 1 records and 0 guild scores"));
+        apply_diff(&diff, &conn).unwrap();
+
+        let ids: Vec<i32> = Song::all(&conn)
+            .unwrap()
+            .iter()
+            .map(|song| song.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(Song::player_data(2, &conn).unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn skips_aliases_of_removed_songs() {
+        let conn = db::establish_connection().unwrap();
+
+        let songs = read("aliased", &[STAY_WITH_ME, TWO_NITE, ARMORED_PHANTOM]).unwrap();
+        apply_diff(&diff_songs(songs, &conn).unwrap(), &conn).unwrap();
+        Record::save(
+            &Record {
+                user_id: 42,
+                song_id: 2,
+                mode: 4,
+                difficulty: 0,
+                rate: 98.5,
+                max_combo: false,
+                cleared_at: Utc::now().naive_utc(),
+            },
+            &conn,
+        )
+        .unwrap();
+
+        let songs = read("unaliased", &[STAY_WITH_ME]).unwrap();
+        let ids = songs.keys().copied().collect();
+        let mut diff = diff_songs(songs, &conn).unwrap();
+        let aliases = [
+            (1, "비상"),
+            (2, "투나잇"),
+            (3, "아머드 팬텀"),
+            (4, "없는 곡"),
+        ]
+        .iter()
+        .map(|&(song_id, alias)| SongAlias {
+            song_id,
+            alias: alias.to_string(),
+        })
+        .collect();
+        let (aliases, skipped) = split_aliases(aliases, &ids, &diff);
+        assert_eq!(
+            skipped
+                .iter()
+                .map(|alias| alias.song_id)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        diff.skipped_aliases = skipped;
+        assert!(diff
+            .to_string()
+            .contains("Alias 아머드 팬텀 of missing song 3 is skipped"));
+
+        apply_diff(&diff, &conn).unwrap();
+        for alias in &aliases {
+            SongAlias::create(alias, &conn).unwrap();
+        }
+        let ids: Vec<i32> = SongAlias::all(&conn)
+            .unwrap()
+            .iter()
+            .map(|alias| alias.song_id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn rejects_duplicate_songs() {
+        assert!(read("duplicate", &[STAY_WITH_ME, STAY_WITH_ME]).is_err());
+    }
+
+    #[test]
+    fn reads_optional_chart_details() {
+        let conn = db::establish_connection().unwrap();
+        apply_diff(
+            &diff_songs(read("plain", &[STAY_WITH_ME]).unwrap(), &conn).unwrap(),
+            &conn,
+        )
+        .unwrap();
+
+        let header = format!("{},6b2Notes,6b2LongNotes,6b2Designer,8b0Notes", HEADER);
+        let row = format!("{},1203,85,Forte Escape,", STAY_WITH_ME);
+        let songs = read_with_header("details", &header, &[&row]).unwrap();
+        let chart = songs[&1]
+            .pattern(ButtonMode::Six, Difficulty::Maximum)
+            .unwrap();
+        assert_eq!(chart.note_count, Some(1203));
+        assert_eq!(chart.long_note_count, Some(85));
+        assert_eq!(chart.designer.as_deref(), Some("Forte Escape"));
+        let chart = songs[&1]
+            .pattern(ButtonMode::Eight, Difficulty::Normal)
+            .unwrap();
+        assert!(!chart.has_details());
+
+        let diff = diff_songs(songs, &conn).unwrap();
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.len(), 3);
+        apply_diff(&diff, &conn).unwrap();
+        let song = &Song::all(&conn).unwrap()[0];
+        let chart = song.pattern(ButtonMode::Six, Difficulty::Maximum).unwrap();
+        assert_eq!(chart.note_count, Some(1203));
+
+        let row = format!("{},many,,,", STAY_WITH_ME);
+        assert!(read_with_header("invalid", &header, &[&row]).is_err());
+    }
+}
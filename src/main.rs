@@ -3,36 +3,73 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+mod catalog;
 mod db;
 mod discord;
+mod fuzzy;
+mod hangul;
+mod import;
+mod rating;
 
 use std::error::Error;
-use std::fs::File;
 use std::env;
 
+use diesel::connection::Connection;
+use diesel::result::Error::RollbackTransaction;
+use diesel::sqlite::SqliteConnection;
 use dotenv::dotenv;
 
-use crate::db::{establish_connection, models::Song};
+use crate::db::{establish_connection, run_migrations};
+
+/// Brings the schema up to date before anything touches the database.
+fn migrate(conn: &SqliteConnection) -> Result<(), Box<dyn Error>> {
+    let applied = run_migrations(conn)
+        .map_err(|why| format!("Refusing to start, the database could not be migrated: {}", why))?;
+    if applied.is_empty() {
+        println!("Database schema is up to date");
+    }
+    for version in &applied {
+        println!("Applied migration {}", version);
+    }
+    Ok(())
+}
+
+/// Reports what migrating and importing the CSV files would change. Both run
+/// in a transaction that is always rolled back, so the database is untouched.
+fn dry_run(conn: &SqliteConnection) -> Result<(), Box<dyn Error>> {
+    let mut diff = None;
+    let rolled_back = conn.transaction::<(), Box<dyn Error>, _>(|| {
+        migrate(conn)?;
+        diff = Some(import::diff_csv(conn)?);
+        Err(RollbackTransaction.into())
+    });
+    match diff {
+        Some(diff) => {
+            print!("{}", diff);
+            println!("Dry run, nothing was written to the database");
+            Ok(())
+        }
+        None => rolled_back,
+    }
+}
 
 fn run() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
 
-    let file_path = env::var("CSV_FILE_NAME")
-        .expect("CSV_FILE_NAME must be set");
-    let file = File::open(file_path)?;
-    let mut rdr = csv::Reader::from_reader(file);
+    let conn = establish_connection()?;
 
-    let conn = establish_connection();
-
-    for result in rdr.deserialize() {
-        let song: Song = result?;
-        Song::create_or_update(&song, &conn);
+    // `--dry-run` only reports what starting the bot would change
+    if env::args().skip(1).any(|arg| arg == "--dry-run") {
+        return dry_run(&conn);
     }
 
+    migrate(&conn)?;
+    print!("{}", import::import_csv(&conn)?);
+
     // start listening for events by starting a single shard
     let token = env::var("DISCORD_TOKEN")
         .expect("Expected a token in the environment");
-    let mut client = discord::establish_client(&token);
+    let mut client = discord::establish_client(&token)?;
     client.start()?;
 
     Ok(())
@@ -0,0 +1,474 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::db::models::{ButtonMode, Difficulty, Record, Song};
+
+/// Number of best charts that count towards a rating, as in DJ POWER.
+pub const TOP_CHARTS: usize = 50;
+
+/// Points of a chart played at 100% are ten times its level.
+const POINTS_PER_LEVEL: f64 = 10.0;
+
+/// Max combos are worth 5% more than the same rate without one.
+const MAX_COMBO_BONUS: f64 = 1.05;
+
+/// Points earned by a single record.
+#[derive(Debug)]
+pub struct ChartPoint {
+    pub title: String,
+    pub difficulty: Difficulty,
+    pub level: i32,
+    pub rate: f64,
+    pub max_combo: bool,
+    pub point: f64,
+}
+
+/// Rating of a player in a button mode and the charts it is made of.
+#[derive(Debug)]
+pub struct Rating {
+    pub power: f64,
+    /// The charts counted in `power`, best first.
+    pub charts: Vec<ChartPoint>,
+}
+
+/// Weighs the level of a chart by the accuracy it was played with. The rate
+/// is raised to the fourth power so that 99% is worth far more than 95%.
+pub fn chart_point(level: i32, rate: f64, max_combo: bool) -> f64 {
+    let accuracy = (rate / 100.0).powi(4);
+    let bonus = if max_combo { MAX_COMBO_BONUS } else { 1.0 };
+    f64::from(level) * POINTS_PER_LEVEL * accuracy * bonus
+}
+
+/// Sums the points of the `TOP_CHARTS` best records. `songs` must contain the
+/// song of every record; records of unknown charts are ignored.
+pub fn rate(records: &[Record], songs: &[Song]) -> Rating {
+    let mut charts: Vec<ChartPoint> = records
+        .iter()
+        .filter_map(|record| {
+            let song = songs.iter().find(|song| song.id == record.song_id)?;
+            let mode = record.button_mode()?;
+            let difficulty = record.chart_difficulty()?;
+            let level = song.level(mode, difficulty)?;
+            Some(ChartPoint {
+                title: song.title.clone(),
+                difficulty,
+                level,
+                rate: record.rate,
+                max_combo: record.max_combo,
+                point: chart_point(level, record.rate, record.max_combo),
+            })
+        })
+        .collect();
+    charts.sort_by(|a, b| {
+        b.point
+            .partial_cmp(&a.point)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.title.cmp(&b.title))
+            .then_with(|| a.difficulty.index().cmp(&b.difficulty.index()))
+    });
+    charts.truncate(TOP_CHARTS);
+
+    Rating {
+        power: charts.iter().map(|chart| chart.point).sum(),
+        charts,
+    }
+}
+
+/// Number of best charts whose levels and rates make up a player's comfort zone.
+const COMFORT_CHARTS: usize = 10;
+
+/// Number of charts suggested at once.
+pub const RECOMMENDATIONS: usize = 10;
+
+/// Level and rate a player typically achieves, from their best charts.
+#[derive(Debug, Clone, Copy)]
+pub struct Comfort {
+    pub level: f64,
+    pub rate: f64,
+}
+
+impl Rating {
+    /// Averages the levels and rates of the best charts, or `None` without records.
+    pub fn comfort(&self) -> Option<Comfort> {
+        let best = &self.charts[..self.charts.len().min(COMFORT_CHARTS)];
+        if best.is_empty() {
+            return None;
+        }
+        let count = best.len() as f64;
+        Some(Comfort {
+            level: best.iter().map(|chart| f64::from(chart.level)).sum::<f64>() / count,
+            rate: best.iter().map(|chart| chart.rate).sum::<f64>() / count,
+        })
+    }
+}
+
+/// Why a chart was suggested.
+#[derive(Debug)]
+pub enum Reason {
+    /// Not played yet and around the comfort level.
+    Unplayed { comfort: f64 },
+    /// Not played yet and above the comfort level.
+    Challenge { comfort: f64 },
+    /// Played below the usual rate.
+    Improvable { rate: f64, typical: f64 },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Unplayed { comfort } => {
+                write!(f, "아직 기록이 없는 패턴 (평소 레벨 {:.1})", comfort)
+            }
+            Reason::Challenge { comfort } => {
+                write!(f, "평소 레벨 {:.1}보다 조금 높은 도전 패턴", comfort)
+            }
+            Reason::Improvable { rate, typical } => write!(
+                f,
+                "현재 {:.2}%, 평소 정확도 {:.2}%까지 올릴 여지가 있음",
+                rate, typical
+            ),
+        }
+    }
+}
+
+/// A chart suggested to practice next.
+#[derive(Debug)]
+pub struct Recommendation {
+    pub title: String,
+    pub difficulty: Difficulty,
+    pub level: i32,
+    pub reason: Reason,
+}
+
+/// Suggests charts of `mode` among `candidates` whose level lies within one of
+/// the rounded comfort level: half of them improvable records, biggest point gain
+/// first, and half of them unplayed charts, closest to the comfort level first.
+pub fn recommend<R: Rng>(
+    mode: ButtonMode,
+    comfort: Comfort,
+    records: &[Record],
+    candidates: &[Song],
+    rng: &mut R,
+) -> Vec<Recommendation> {
+    let center = comfort.level.round() as i32;
+    let mut improvable = Vec::new();
+    let mut unplayed = Vec::new();
+    for song in candidates {
+        for &difficulty in &Difficulty::ALL {
+            let level = match song.level(mode, difficulty) {
+                Some(level) if (level - center).abs() <= 1 => level,
+                _ => continue,
+            };
+            let record = records.iter().find(|record| {
+                record.song_id == song.id
+                    && record.button_mode() == Some(mode)
+                    && record.chart_difficulty() == Some(difficulty)
+            });
+            match record {
+                Some(record) if record.rate < comfort.rate => {
+                    let gain = chart_point(level, comfort.rate, record.max_combo)
+                        - chart_point(level, record.rate, record.max_combo);
+                    let reason = Reason::Improvable {
+                        rate: record.rate,
+                        typical: comfort.rate,
+                    };
+                    improvable.push((gain, recommendation(song, difficulty, level, reason)));
+                }
+                Some(_) => {}
+                None => {
+                    let reason = if level > center {
+                        Reason::Challenge {
+                            comfort: comfort.level,
+                        }
+                    } else {
+                        Reason::Unplayed {
+                            comfort: comfort.level,
+                        }
+                    };
+                    unplayed.push(recommendation(song, difficulty, level, reason));
+                }
+            }
+        }
+    }
+
+    improvable.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    // Shuffling first varies the suggestions among charts of the same level.
+    unplayed.shuffle(rng);
+    unplayed.sort_by(|a, b| {
+        let distance = |chart: &Recommendation| (f64::from(chart.level) - comfort.level).abs();
+        distance(a)
+            .partial_cmp(&distance(b))
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut improvable = improvable.into_iter().map(|(_, chart)| chart);
+    let mut unplayed = unplayed.into_iter();
+    let mut suggestions: Vec<Recommendation> =
+        improvable.by_ref().take(RECOMMENDATIONS / 2).collect();
+    suggestions.extend(unplayed.by_ref().take(RECOMMENDATIONS - suggestions.len()));
+    suggestions.extend(improvable.take(RECOMMENDATIONS - suggestions.len()));
+    suggestions
+}
+
+fn recommendation(
+    song: &Song,
+    difficulty: Difficulty,
+    level: i32,
+    reason: Reason,
+) -> Recommendation {
+    Recommendation {
+        title: song.title.clone(),
+        difficulty,
+        level,
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::Pattern;
+    use chrono::NaiveDateTime;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// A song with one 4B chart per `(difficulty, level)`.
+    fn song(id: i32, charts: &[(Difficulty, i32)]) -> Song {
+        Song {
+            id,
+            title: format!("Song {:02}", id),
+            artist: "Artist".to_string(),
+            min_bpm: None,
+            max_bpm: 150.0,
+            category: "RP".to_string(),
+            dlc: None,
+            patterns: charts
+                .iter()
+                .map(|&(difficulty, level)| Pattern {
+                    song_id: id,
+                    mode: ButtonMode::Four.buttons(),
+                    difficulty: difficulty.index(),
+                    level,
+                    note_count: None,
+                    long_note_count: None,
+                    designer: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn record(song_id: i32, difficulty: Difficulty, rate: f64, max_combo: bool) -> Record {
+        Record {
+            user_id: 1,
+            song_id,
+            mode: ButtonMode::Four.buttons(),
+            difficulty: difficulty.index(),
+            rate,
+            max_combo,
+            cleared_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn chart_point_weighs_level_by_rate() {
+        assert_eq!(chart_point(10, 100.0, false), 100.0);
+        assert_eq!(chart_point(10, 100.0, true), 105.0);
+        assert_eq!(chart_point(10, 50.0, false), 6.25);
+        assert!(chart_point(12, 99.0, false) > chart_point(12, 95.0, true));
+    }
+
+    #[test]
+    fn rate_counts_the_best_charts_of_known_songs() {
+        let songs: Vec<Song> = (1..=60)
+            .map(|id| song(id, &[(Difficulty::Normal, id % 15 + 1)]))
+            .collect();
+        let mut records: Vec<Record> = (1..=60)
+            .map(|id| record(id, Difficulty::Normal, 100.0, false))
+            .collect();
+        // Neither the song nor the chart exists.
+        records.push(record(99, Difficulty::Normal, 100.0, true));
+        records.push(record(1, Difficulty::SC, 100.0, true));
+
+        let rating = rate(&records, &songs);
+        assert_eq!(rating.charts.len(), TOP_CHARTS);
+        assert!(rating
+            .charts
+            .windows(2)
+            .all(|pair| pair[0].point >= pair[1].point));
+
+        let mut points: Vec<f64> = (1..=60)
+            .map(|id| chart_point(id % 15 + 1, 100.0, false))
+            .collect();
+        points.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let expected: f64 = points[..TOP_CHARTS].iter().sum();
+        assert!((rating.power - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rate_breaks_ties_by_title_then_difficulty() {
+        let songs = vec![
+            song(2, &[(Difficulty::Normal, 8)]),
+            song(1, &[(Difficulty::Normal, 8), (Difficulty::Hard, 8)]),
+        ];
+        let records = vec![
+            record(2, Difficulty::Normal, 98.0, false),
+            record(1, Difficulty::Hard, 98.0, false),
+            record(1, Difficulty::Normal, 98.0, false),
+        ];
+
+        let charts: Vec<(String, Difficulty)> = rate(&records, &songs)
+            .charts
+            .into_iter()
+            .map(|chart| (chart.title, chart.difficulty))
+            .collect();
+        assert_eq!(
+            charts,
+            vec![
+                ("Song 01".to_string(), Difficulty::Normal),
+                ("Song 01".to_string(), Difficulty::Hard),
+                ("Song 02".to_string(), Difficulty::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn comfort_averages_the_best_charts() {
+        let empty = Rating {
+            power: 0.0,
+            charts: vec![],
+        };
+        assert!(empty.comfort().is_none());
+
+        // Ten level 10 charts at 98%, then two level 1 charts that do not count.
+        let songs: Vec<Song> = (1..=12)
+            .map(|id| song(id, &[(Difficulty::Normal, if id <= 10 { 10 } else { 1 })]))
+            .collect();
+        let records: Vec<Record> = (1..=12)
+            .map(|id| {
+                record(
+                    id,
+                    Difficulty::Normal,
+                    if id <= 10 { 98.0 } else { 90.0 },
+                    false,
+                )
+            })
+            .collect();
+        let comfort = rate(&records, &songs).comfort().unwrap();
+        assert_eq!(comfort.level, 10.0);
+        assert_eq!(comfort.rate, 98.0);
+    }
+
+    const COMFORT: Comfort = Comfort {
+        level: 10.0,
+        rate: 98.0,
+    };
+
+    fn titles(suggestions: &[Recommendation]) -> Vec<&str> {
+        suggestions
+            .iter()
+            .map(|chart| chart.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn recommend_splits_improvable_and_unplayed_charts() {
+        // Songs 1-7 are played at level 10, worse the higher the id; song 8 is
+        // played better than usual. Songs 11-17 are unplayed between levels 9
+        // and 11, songs 18 and 19 fall outside the level window.
+        let mut songs: Vec<Song> = (1..=8)
+            .map(|id| song(id, &[(Difficulty::Normal, 10)]))
+            .collect();
+        songs.extend((11..=17).map(|id| song(id, &[(Difficulty::Hard, id % 3 + 9)])));
+        songs.push(song(18, &[(Difficulty::SC, 8)]));
+        songs.push(song(19, &[(Difficulty::SC, 12)]));
+        let mut records: Vec<Record> = (1..=7)
+            .map(|id| record(id, Difficulty::Normal, 97.0 - f64::from(id), false))
+            .collect();
+        records.push(record(8, Difficulty::Normal, 99.0, false));
+
+        let suggestions = recommend(
+            ButtonMode::Four,
+            COMFORT,
+            &records,
+            &songs,
+            &mut StdRng::seed_from_u64(7),
+        );
+        assert_eq!(suggestions.len(), RECOMMENDATIONS);
+
+        let (improvable, unplayed) = suggestions.split_at(RECOMMENDATIONS / 2);
+        assert_eq!(
+            titles(improvable),
+            vec!["Song 07", "Song 06", "Song 05", "Song 04", "Song 03"]
+        );
+        assert!(improvable
+            .iter()
+            .all(|chart| matches!(chart.reason, Reason::Improvable { .. })));
+
+        // Level 10 charts come before the ones a level away.
+        let levels: Vec<i32> = unplayed.iter().map(|chart| chart.level).collect();
+        assert_eq!(&levels[..2], &[10, 10]);
+        assert!(levels.iter().all(|&level| (9..=11).contains(&level)));
+        for chart in unplayed {
+            match chart.reason {
+                Reason::Challenge { .. } => assert_eq!(chart.level, 11),
+                Reason::Unplayed { .. } => assert!(chart.level <= 10),
+                Reason::Improvable { .. } => panic!("{} was played", chart.title),
+            }
+        }
+
+        let again = recommend(
+            ButtonMode::Four,
+            COMFORT,
+            &records,
+            &songs,
+            &mut StdRng::seed_from_u64(7),
+        );
+        assert_eq!(titles(&again), titles(&suggestions));
+    }
+
+    #[test]
+    fn recommend_fills_up_with_the_other_kind() {
+        // Seven improvable charts and two unplayed ones.
+        let mut songs: Vec<Song> = (1..=7)
+            .map(|id| song(id, &[(Difficulty::Normal, 10)]))
+            .collect();
+        songs.push(song(11, &[(Difficulty::Hard, 10)]));
+        songs.push(song(12, &[(Difficulty::Hard, 9)]));
+        let records: Vec<Record> = (1..=7)
+            .map(|id| record(id, Difficulty::Normal, 97.0 - f64::from(id), false))
+            .collect();
+
+        let suggestions = recommend(
+            ButtonMode::Four,
+            COMFORT,
+            &records,
+            &songs,
+            &mut StdRng::seed_from_u64(7),
+        );
+        assert_eq!(
+            titles(&suggestions),
+            vec![
+                "Song 07", "Song 06", "Song 05", "Song 04", "Song 03", "Song 11", "Song 12",
+                "Song 02", "Song 01",
+            ]
+        );
+
+        // Two improvable charts and nine unplayed ones.
+        let records = &records[..2];
+        let suggestions = recommend(
+            ButtonMode::Four,
+            COMFORT,
+            records,
+            &songs,
+            &mut StdRng::seed_from_u64(7),
+        );
+        assert_eq!(suggestions.len(), 9);
+        assert_eq!(titles(&suggestions[..2]), vec!["Song 02", "Song 01"]);
+        assert!(suggestions[2..]
+            .iter()
+            .all(|chart| matches!(chart.reason, Reason::Unplayed { .. })));
+    }
+}